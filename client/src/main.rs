@@ -1,15 +1,128 @@
 use ev::MouseEvent;
 use gloo_net::http::Request;
 use leptos::*;
-use logging::log;
 use serde::{Deserialize, Serialize};
-const SERVER: &'static str = "<Your server here>";
+use std::cmp::Ordering;
+use wasm_bindgen::JsValue;
+const SERVER: &str = "<Your server here>";
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 struct ResponseTask {
     task_id: i64,
+    #[serde(default)]
+    list_id: Option<i64>,
+    #[serde(default)]
+    parent_id: Option<i64>,
+    #[serde(default)]
+    position: i64,
     task_title: String,
     task_description: String,
+    done: bool,
+    completed_at: Option<String>,
+    due_at: Option<String>,
+    start_at: Option<String>,
+    priority: i64,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    recurrence: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ResponseTag {
+    tag_id: i64,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct ResponseList {
+    list_id: i64,
+    name: String,
+    open_tasks: i64,
+}
+
+// The URL of the user's calendar feed, `None` while it is disabled.
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct FeedInfo {
+    url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ResponseAppPassword {
+    app_password_id: i64,
+    name: String,
+    created_at: String,
+}
+
+// `password` is only set right after creating one, the only time it is shown.
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct AppPasswordResponse {
+    app_passwords: Vec<ResponseAppPassword>,
+    password: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct AppPasswordInfo {
+    name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ImportIssue {
+    row: usize,
+    title: String,
+    message: String,
+}
+
+// What an import did, or would do on a dry run; nothing is imported while
+// there are errors.
+#[derive(Serialize, Deserialize, Clone)]
+struct ImportResponse {
+    dry_run: bool,
+    imported: bool,
+    created: usize,
+    lists_created: Vec<String>,
+    duplicates: Vec<ImportIssue>,
+    errors: Vec<ImportIssue>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ListResponse {
+    lists: Vec<ResponseList>,
+    success: bool,
+    message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SearchHit {
+    task_id: i64,
+    list_id: i64,
+    parent_id: Option<i64>,
+    done: bool,
+    // HTML with the matched words in <mark>, escaped by the server
+    title: String,
+    snippet: String,
+    rank: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SearchResponse {
+    results: Vec<SearchHit>,
+    success: bool,
+    message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct TagResponse {
+    tags: Vec<ResponseTag>,
+    success: bool,
+    message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct TaskGroups {
+    overdue: Vec<i64>,
+    today: Vec<i64>,
+    upcoming: Vec<i64>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -17,16 +130,193 @@ struct Response {
     user_id: i64,
     username: String,
     tasks: Vec<ResponseTask>,
+    #[serde(default)]
+    groups: TaskGroups,
+    #[serde(default)]
+    revision: i64,
     success: bool,
     message: String,
 }
 
+// Answer of a task mutation sent with `view=changes`: only the touched tasks.
+#[derive(Serialize, Deserialize, Clone)]
+struct TaskChanges {
+    revision: i64,
+    tasks: Vec<ResponseTask>,
+    removed: Vec<i64>,
+    groups: TaskGroups,
+    success: bool,
+    message: String,
+}
+
+// Body of every failed request; branch on `code`, show `message`.
+#[derive(Deserialize, Clone)]
+struct ApiError {
+    code: String,
+    message: String,
+    #[serde(default)]
+    fields: Vec<FieldError>,
+    #[serde(default)]
+    request_id: String,
+}
+
+#[derive(Deserialize, Clone)]
+struct FieldError {
+    field: String,
+    message: String,
+}
+
+// Body of PATCH /tasks/{id}; the client always sends every editable field.
+#[derive(Serialize, Deserialize, Clone)]
+struct TaskPatch {
+    task_title: String,
+    task_description: String,
+    due_at: Option<String>,
+    start_at: Option<String>,
+    priority: i64,
+    tags: Vec<String>,
+    recurrence: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct DoneChange {
+    task_id: i64,
+    done: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ListChange {
+    task_id: i64,
+    list_id: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ChildrenOrder {
+    task_id: i64,
+    children: Vec<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct PositionInfo {
+    task_id: i64,
+    before_id: Option<i64>,
+    after_id: Option<i64>,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct LoginInfo {
     username: String,
     password: String,
 }
 
+// Minutes east of UTC in the browser's timezone, sent along so the server can
+// tell which tasks are due today.
+fn tz_offset() -> i32 {
+    -(js_sys::Date::new_0().get_timezone_offset() as i32)
+}
+
+// A datetime-local input value is in the browser's timezone; the server wants RFC 3339.
+fn local_input_to_rfc3339(value: &str) -> Option<String> {
+    let date = js_sys::Date::new(&JsValue::from_str(value));
+    if value.is_empty() || date.get_time().is_nan() {
+        None
+    } else {
+        Some(date.to_iso_string().into())
+    }
+}
+
+fn rfc3339_to_local_input(value: &Option<String>) -> String {
+    match value {
+        Some(value) => {
+            let date = js_sys::Date::new(&JsValue::from_str(value));
+            format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}",
+                date.get_full_year(),
+                date.get_month() + 1,
+                date.get_date(),
+                date.get_hours(),
+                date.get_minutes()
+            )
+        }
+        None => "".to_string(),
+    }
+}
+
+// Tags are typed as a comma separated list.
+fn parse_tags(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+// The error of a failed request, also for answers that did not come from the
+// API itself, e.g. a proxy error page.
+async fn api_error(response: gloo_net::http::Response) -> ApiError {
+    match response.json::<ApiError>().await {
+        Ok(error) => error,
+        Err(_) => ApiError {
+            code: "http_error".to_string(),
+            message: format!("Request failed with status {}", response.status()),
+            fields: vec![],
+            request_id: "".to_string(),
+        },
+    }
+}
+
+async fn task_changes(response: gloo_net::http::Response) -> Result<TaskChanges, ApiError> {
+    if response.ok() {
+        Ok(response.json().await.unwrap())
+    } else {
+        Err(api_error(response).await)
+    }
+}
+
+fn anonymous(message: &str) -> Response {
+    Response {
+        user_id: -1,
+        username: "Anon".to_string(),
+        tasks: vec![],
+        groups: TaskGroups::default(),
+        revision: 0,
+        success: false,
+        message: message.to_string(),
+    }
+}
+
+// Same order as the server's for the given sort key and order, so patched in
+// tasks land where a reload would put them.
+fn sort_tasks(tasks: &mut [ResponseTask], sort: &str, order: &str) {
+    let descending = match order {
+        "asc" => false,
+        "desc" => true,
+        _ => sort == "priority",
+    };
+    tasks.sort_by(|a, b| {
+        let undated = match sort {
+            "due_date" => a.due_at.is_none().cmp(&b.due_at.is_none()),
+            _ => Ordering::Equal,
+        };
+        let ordering = match sort {
+            "priority" => a.priority.cmp(&b.priority),
+            "due_date" => a.due_at.cmp(&b.due_at),
+            "created" => a.task_id.cmp(&b.task_id),
+            "title" => a
+                .task_title
+                .to_lowercase()
+                .cmp(&b.task_title.to_lowercase()),
+            _ => a.position.cmp(&b.position),
+        };
+        let ordering = if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        undated.then(ordering).then(a.task_id.cmp(&b.task_id))
+    });
+}
+
 fn main() {
     console_error_panic_hook::set_once();
     mount_to_body(|| view! {<App />})
@@ -36,28 +326,221 @@ fn main() {
 fn App() -> impl IntoView {
     let (reload_needed, set_reload_needed) = create_signal(true);
 
-    let (data, set_data) = create_signal(Response {
-        user_id: -1,
-        username: "Anon".to_string(),
-        tasks: vec![],
-        success: false,
-        message: "SessionGetError".to_string(),
-    });
+    let (sort_key, set_sort_key) = create_signal("manual".to_string());
+    let (sort_order, set_sort_order) = create_signal("".to_string());
+    let (tag_filter, set_tag_filter) = create_signal(Vec::<String>::new());
+    let (selected_list, set_selected_list) = create_signal(None::<i64>);
+    // Query string for every request that answers with the task list.
+    let list_query = move || {
+        let mut query = format!(
+            "tz_offset={}&sort={}",
+            tz_offset(),
+            sort_key.get_untracked()
+        );
+        if !sort_order.get_untracked().is_empty() {
+            query.push_str(&format!("&order={}", sort_order.get_untracked()));
+        }
+        if let Some(list_id) = selected_list.get_untracked() {
+            query.push_str(&format!("&list_id={}", list_id));
+        }
+        if !tag_filter.get_untracked().is_empty() {
+            let tags: String =
+                js_sys::encode_uri_component(&tag_filter.get_untracked().join(",")).into();
+            query.push_str(&format!("&tags={}", tags));
+        }
+        query
+    };
 
-    create_effect(move |_| {
+    let (data, set_data) = create_signal(anonymous("Not logged in!"));
+
+    let (tags, set_tags) = create_signal(Vec::<ResponseTag>::new());
+    // Tags can be created by any task create or update, so they are reloaded
+    // after those.
+    let reload_tags = move || {
         spawn_local(async move {
-            if reload_needed.get() {
-                let fetched_response: Response = Request::get(&format!("{}/data", SERVER))
+            let response = Request::get(&format!("{}/tags", SERVER))
+                .credentials(web_sys::RequestCredentials::Include)
+                .send()
+                .await
+                .unwrap();
+            if response.ok() {
+                let fetched_response: TagResponse = response.json().await.unwrap();
+                set_tags.set(fetched_response.tags);
+            } else {
+                set_tags.set(vec![]);
+            }
+        })
+    };
+
+    let (lists, set_lists) = create_signal(Vec::<ResponseList>::new());
+    // The open task counts change with most task mutations.
+    let reload_lists = move || {
+        spawn_local(async move {
+            let response = Request::get(&format!("{}/lists", SERVER))
+                .credentials(web_sys::RequestCredentials::Include)
+                .send()
+                .await
+                .unwrap();
+            if response.ok() {
+                let fetched_response: ListResponse = response.json().await.unwrap();
+                set_lists.set(fetched_response.lists);
+            } else {
+                set_lists.set(vec![]);
+            }
+        })
+    };
+
+    let (feed, set_feed) = create_signal(FeedInfo::default());
+    let reload_feed = move || {
+        spawn_local(async move {
+            let response = Request::get(&format!("{}/feed", SERVER))
+                .credentials(web_sys::RequestCredentials::Include)
+                .send()
+                .await
+                .unwrap();
+            if response.ok() {
+                set_feed.set(response.json().await.unwrap());
+            } else {
+                set_feed.set(FeedInfo::default());
+            }
+        })
+    };
+
+    let (app_passwords, set_app_passwords) = create_signal(AppPasswordResponse::default());
+    let reload_app_passwords = move || {
+        spawn_local(async move {
+            let response = Request::get(&format!("{}/app-passwords", SERVER))
+                .credentials(web_sys::RequestCredentials::Include)
+                .send()
+                .await
+                .unwrap();
+            if response.ok() {
+                set_app_passwords.set(response.json().await.unwrap());
+            } else {
+                set_app_passwords.set(AppPasswordResponse::default());
+            }
+        })
+    };
+
+    let reload_list = move || {
+        spawn_local(async move {
+            let fetched_response: Response =
+                Request::get(&format!("{}/data?{}", SERVER, list_query()))
                     .credentials(web_sys::RequestCredentials::Include)
-                    .header("access-control-allow-origin", "*")
                     .send()
                     .await
                     .unwrap()
                     .json()
                     .await
                     .unwrap();
+            set_data.set(fetched_response);
+        })
+    };
+
+    // Failed requests keep the current list and only report why, except for a
+    // lost session, which signs the user out.
+    let report_error = move |error: ApiError| {
+        let message = match error.code.as_str() {
+            "unauthorized" => {
+                return set_data.set(anonymous("Your session has ended, please sign in again"))
+            }
+            "unavailable" => "The server is busy, please try again".to_string(),
+            "internal" | "session_error" => {
+                format!("Something went wrong (request {})", error.request_id)
+            }
+            _ => error.message,
+        };
+        set_data.update(|data| {
+            data.success = false;
+            data.message = message;
+        })
+    };
+
+    // Field errors of the last failed submit, shown next to the inputs of the
+    // form that sent it: "login", "register", "list", "new_task", a task id for
+    // the task being edited and "subtask:<parent id>".
+    let (field_errors, set_field_errors) = create_signal((String::new(), Vec::<FieldError>::new()));
+    let field_error = move |form: &str, fields: &[&str]| {
+        let (failed_form, errors) = field_errors.get();
+        let messages: Vec<String> = errors
+            .into_iter()
+            .filter(|error| failed_form == form && fields.contains(&error.field.as_str()))
+            .map(|error| error.message)
+            .collect();
+        (!messages.is_empty()).then(|| messages.join("; "))
+    };
+    let set_form_errors = move |form: &str, error: Option<&ApiError>| {
+        let fields = error.map(|error| error.fields.clone()).unwrap_or_default();
+        set_field_errors.set((form.to_string(), fields));
+    };
+
+    // Patches the list with the tasks a mutation touched. A revision gap means
+    // the list missed a change made elsewhere, so it is reloaded instead.
+    let apply_changes = move |changes: Result<TaskChanges, ApiError>| {
+        let changes = match changes {
+            Ok(changes) => changes,
+            Err(error) => return report_error(error),
+        };
+        if changes.revision != data.get_untracked().revision + 1 {
+            return reload_list();
+        }
+        set_data.update(|data| {
+            let changed: Vec<i64> = changes
+                .tasks
+                .iter()
+                .map(|task| task.task_id)
+                .chain(changes.removed.iter().copied())
+                .collect();
+            data.tasks
+                .retain(|task| !changes.removed.contains(&task.task_id));
+            for task in changes.tasks {
+                match data
+                    .tasks
+                    .iter_mut()
+                    .find(|old| old.task_id == task.task_id)
+                {
+                    Some(old) => *old = task,
+                    None => data.tasks.push(task),
+                }
+            }
+            sort_tasks(
+                &mut data.tasks,
+                &sort_key.get_untracked(),
+                &sort_order.get_untracked(),
+            );
+            for (group, added) in [
+                (&mut data.groups.overdue, changes.groups.overdue),
+                (&mut data.groups.today, changes.groups.today),
+                (&mut data.groups.upcoming, changes.groups.upcoming),
+            ] {
+                group.retain(|task_id| !changed.contains(task_id));
+                group.extend(added);
+            }
+            data.revision = changes.revision;
+            data.success = changes.success;
+            data.message = changes.message;
+        })
+    };
+
+    create_effect(move |_| {
+        spawn_local(async move {
+            if reload_needed.get() {
+                let fetched_response: Response =
+                    Request::get(&format!("{}/data?{}", SERVER, list_query()))
+                        .credentials(web_sys::RequestCredentials::Include)
+                        .header("access-control-allow-origin", "*")
+                        .send()
+                        .await
+                        .unwrap()
+                        .json()
+                        .await
+                        .unwrap();
                 set_data.set(fetched_response);
                 set_reload_needed.set(false);
+                reload_tags();
+                reload_lists();
+                reload_feed();
+                reload_app_passwords();
             }
         })
     });
@@ -72,62 +555,157 @@ fn App() -> impl IntoView {
             password: password.get(),
         };
         spawn_local(async move {
-            let fetched_response = Request::post(&format!("{}/login", SERVER))
+            let response = Request::post(&format!("{}/login?{}", SERVER, list_query()))
                 .credentials(web_sys::RequestCredentials::Include)
                 .json(&login_info)
                 .unwrap()
                 .send()
                 .await
+                .unwrap();
+            if !response.ok() {
+                let error = api_error(response).await;
+                if error.code == "invalid_credentials" {
+                    set_password.set("".to_string());
+                }
+                set_form_errors("login", Some(&error));
+                report_error(error);
+                return;
+            }
+            set_form_errors("login", None);
+
+            set_data.set(response.json().await.unwrap());
+            reload_tags();
+            reload_lists();
+            reload_feed();
+            reload_app_passwords();
+        })
+    };
+
+    let (new_username, set_new_username) = create_signal("".to_string());
+    let (new_password, set_new_password) = create_signal("".to_string());
+
+    let on_register_info_submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        let login_info = LoginInfo {
+            username: new_username.get(),
+            password: new_password.get(),
+        };
+        spawn_local(async move {
+            let response = Request::post(&format!("{}/register", SERVER))
+                .credentials(web_sys::RequestCredentials::Include)
+                .json(&login_info)
                 .unwrap()
-                .json()
+                .send()
                 .await
                 .unwrap();
+            if !response.ok() {
+                let error = api_error(response).await;
+                set_form_errors("register", Some(&error));
+                report_error(error);
+                return;
+            }
+            set_form_errors("register", None);
 
-            set_data.set(fetched_response);
+            set_new_username.set("".to_string());
+            set_new_password.set("".to_string());
+            set_data.set(response.json().await.unwrap());
+            reload_lists();
         })
     };
 
     let on_signout = move |ev: leptos::ev::MouseEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            let fetched_response = Request::delete(&format!("{}/logout", SERVER))
+            let response = Request::delete(&format!("{}/logout", SERVER))
                 .credentials(web_sys::RequestCredentials::Include)
                 .send()
                 .await
-                .unwrap()
-                .json()
+                .unwrap();
+            if response.ok() {
+                set_data.set(response.json().await.unwrap());
+            } else {
+                report_error(api_error(response).await);
+            }
+            set_tags.set(vec![]);
+            set_tag_filter.set(vec![]);
+            set_lists.set(vec![]);
+            set_selected_list.set(None);
+            set_feed.set(FeedInfo::default());
+            set_app_passwords.set(AppPasswordResponse::default());
+        })
+    };
+
+    // Turns the calendar feed on or gives it a new URL (`enable`), or off.
+    let set_feed_enabled = move |enable: bool| {
+        spawn_local(async move {
+            let url = format!("{}/feed", SERVER);
+            let request = if enable {
+                Request::post(&url)
+            } else {
+                Request::delete(&url)
+            };
+            let response = request
+                .credentials(web_sys::RequestCredentials::Include)
+                .send()
                 .await
                 .unwrap();
-            set_data.set(fetched_response);
+            if response.ok() {
+                set_feed.set(response.json().await.unwrap());
+            } else {
+                report_error(api_error(response).await);
+            }
         })
     };
 
     let (selected_task_id, set_selected_task_id) = create_signal(-1);
     let (selected_task_title, set_selected_task_title) = create_signal("".to_string());
     let (selected_task_description, set_selected_task_description) = create_signal("".to_string());
+    let (selected_task_due_at, set_selected_task_due_at) = create_signal("".to_string());
+    let (selected_task_start_at, set_selected_task_start_at) = create_signal("".to_string());
+    let (selected_task_priority, set_selected_task_priority) = create_signal(0);
+    let (selected_task_tags, set_selected_task_tags) = create_signal("".to_string());
+    let (selected_task_recurrence, set_selected_task_recurrence) = create_signal("".to_string());
 
     let on_new_task_submit = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
         spawn_local(async move {
-            let fetched_response: Response = Request::post(&format!("{}/task", SERVER))
-                .credentials(web_sys::RequestCredentials::Include)
-                .json(&ResponseTask {
-                    task_id: -1,
-                    task_title: selected_task_title.get(),
-                    task_description: selected_task_description.get(),
-                })
-                .unwrap()
-                .send()
-                .await
-                .unwrap()
-                .json()
-                .await
-                .unwrap();
+            let response =
+                Request::post(&format!("{}/tasks?view=changes&{}", SERVER, list_query()))
+                    .credentials(web_sys::RequestCredentials::Include)
+                    .json(&ResponseTask {
+                        task_id: -1,
+                        list_id: selected_list.get(),
+                        task_title: selected_task_title.get(),
+                        task_description: selected_task_description.get(),
+                        due_at: local_input_to_rfc3339(&selected_task_due_at.get()),
+                        start_at: local_input_to_rfc3339(&selected_task_start_at.get()),
+                        priority: selected_task_priority.get(),
+                        tags: parse_tags(&selected_task_tags.get()),
+                        recurrence: Some(selected_task_recurrence.get()),
+                        ..Default::default()
+                    })
+                    .unwrap()
+                    .send()
+                    .await
+                    .unwrap();
+            let changes = task_changes(response).await;
+            set_form_errors("new_task", changes.as_ref().err());
+            let created = changes.is_ok();
+            apply_changes(changes);
+            if !created {
+                return;
+            }
 
             set_selected_task_id.set(-1);
             set_selected_task_title.set("".to_string());
             set_selected_task_description.set("".to_string());
-            set_data.set(fetched_response);
+            set_selected_task_due_at.set("".to_string());
+            set_selected_task_start_at.set("".to_string());
+            set_selected_task_priority.set(0);
+            set_selected_task_tags.set("".to_string());
+            set_selected_task_recurrence.set("".to_string());
+            reload_tags();
+            reload_lists();
         })
     };
 
@@ -138,26 +716,46 @@ fn App() -> impl IntoView {
         let task_id: i64 = event_target_value(&ev).parse().unwrap();
         if is_edit_mode.get() && task_id == selected_task_id.get() {
             spawn_local(async move {
-                let fetched_response: Response = Request::put(&format!("{}/task", SERVER))
-                    .credentials(web_sys::RequestCredentials::Include)
-                    .json(&ResponseTask {
-                        task_id: selected_task_id.get(),
-                        task_title: selected_task_title.get(),
-                        task_description: selected_task_description.get(),
-                    })
-                    .unwrap()
-                    .send()
-                    .await
-                    .unwrap()
-                    .json()
-                    .await
-                    .unwrap();
+                let response = Request::patch(&format!(
+                    "{}/tasks/{}?view=changes&{}",
+                    SERVER,
+                    selected_task_id.get(),
+                    list_query()
+                ))
+                .credentials(web_sys::RequestCredentials::Include)
+                .json(&TaskPatch {
+                    task_title: selected_task_title.get(),
+                    task_description: selected_task_description.get(),
+                    due_at: local_input_to_rfc3339(&selected_task_due_at.get()),
+                    start_at: local_input_to_rfc3339(&selected_task_start_at.get()),
+                    priority: selected_task_priority.get(),
+                    tags: parse_tags(&selected_task_tags.get()),
+                    recurrence: Some(selected_task_recurrence.get())
+                        .filter(|rule| !rule.is_empty()),
+                })
+                .unwrap()
+                .send()
+                .await
+                .unwrap();
+                let changes = task_changes(response).await;
+                set_form_errors(&task_id.to_string(), changes.as_ref().err());
+                // a rejected edit stays open so it can be fixed or cancelled
+                let saved = changes.is_ok();
+                apply_changes(changes);
+                if !saved {
+                    return;
+                }
 
-                set_data.set(fetched_response);
                 set_selected_task_id.set(-1);
                 set_selected_task_title.set("".to_string());
                 set_selected_task_description.set("".to_string());
+                set_selected_task_due_at.set("".to_string());
+                set_selected_task_start_at.set("".to_string());
+                set_selected_task_priority.set(0);
+                set_selected_task_tags.set("".to_string());
+                set_selected_task_recurrence.set("".to_string());
                 set_is_edit_mode.set(false);
+                reload_tags();
             })
         } else {
             set_selected_task_id.set(task_id);
@@ -165,41 +763,466 @@ fn App() -> impl IntoView {
                 if task.task_id == task_id {
                     set_selected_task_title.set(task.task_title);
                     set_selected_task_description.set(task.task_description);
-                } else {
+                    set_selected_task_due_at.set(rfc3339_to_local_input(&task.due_at));
+                    set_selected_task_start_at.set(rfc3339_to_local_input(&task.start_at));
+                    set_selected_task_priority.set(task.priority);
+                    set_selected_task_tags.set(task.tags.join(", "));
+                    set_selected_task_recurrence.set(task.recurrence.unwrap_or_default());
                 }
             }
             set_is_edit_mode.set(true);
         }
     };
 
+    let delete_task = move |task_id: i64| {
+        spawn_local(async move {
+            let response = Request::delete(&format!(
+                "{}/tasks/{}?view=changes&{}",
+                SERVER,
+                task_id,
+                list_query()
+            ))
+            .credentials(web_sys::RequestCredentials::Include)
+            .send()
+            .await
+            .unwrap();
+            apply_changes(task_changes(response).await);
+            reload_lists();
+        })
+    };
+
     let on_task_delete_click = move |ev: MouseEvent| {
         ev.prevent_default();
         let task_id: i64 = event_target_value(&ev).parse().unwrap();
         if is_edit_mode.get() {
+            set_form_errors("", None);
             set_selected_task_id.set(-1);
             set_selected_task_title.set("".to_string());
             set_selected_task_description.set("".to_string());
+            set_selected_task_due_at.set("".to_string());
+            set_selected_task_start_at.set("".to_string());
+            set_selected_task_priority.set(0);
+            set_selected_task_tags.set("".to_string());
+            set_selected_task_recurrence.set("".to_string());
             set_is_edit_mode.set(false);
         } else {
-            spawn_local(async move {
-                let fetched_response: Response = Request::delete(&format!("{}/task", SERVER))
+            delete_task(task_id);
+        }
+    };
+
+    // Moves a recurring task on to its next occurrence without completing it.
+    let skip_occurrence = move |task_id: i64| {
+        spawn_local(async move {
+            let response = Request::post(&format!(
+                "{}/tasks/{}/skip?view=changes&{}",
+                SERVER,
+                task_id,
+                list_query()
+            ))
+            .credentials(web_sys::RequestCredentials::Include)
+            .send()
+            .await
+            .unwrap();
+            apply_changes(task_changes(response).await);
+        })
+    };
+
+    let on_task_done_change = move |ev: leptos::ev::Event| {
+        let task_id: i64 = event_target_value(&ev).parse().unwrap();
+        let done = event_target_checked(&ev);
+        spawn_local(async move {
+            let response = Request::put(&format!(
+                "{}/task/done?view=changes&{}",
+                SERVER,
+                list_query()
+            ))
+            .credentials(web_sys::RequestCredentials::Include)
+            .json(&DoneChange { task_id, done })
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+
+            apply_changes(task_changes(response).await);
+            reload_lists();
+        })
+    };
+
+    let move_task = move |task_id: i64, list_id: i64| {
+        spawn_local(async move {
+            let response = Request::put(&format!(
+                "{}/task/list?view=changes&{}",
+                SERVER,
+                list_query()
+            ))
+            .credentials(web_sys::RequestCredentials::Include)
+            .json(&ListChange { task_id, list_id })
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+
+            apply_changes(task_changes(response).await);
+            reload_lists();
+        })
+    };
+
+    // Subtasks of a parent in their stored order.
+    let subtasks = move |parent_id: i64| {
+        let mut subtasks: Vec<ResponseTask> = data
+            .get()
+            .tasks
+            .into_iter()
+            .filter(|task| task.parent_id == Some(parent_id))
+            .collect();
+        subtasks.sort_by_key(|task| (task.position, task.task_id));
+        subtasks
+    };
+
+    let (expanded_tasks, set_expanded_tasks) = create_signal(Vec::<i64>::new());
+
+    let on_subtasks_toggle = move |ev: MouseEvent| {
+        ev.prevent_default();
+        let task_id: i64 = event_target_value(&ev).parse().unwrap();
+        set_expanded_tasks.update(|expanded| {
+            if let Some(index) = expanded.iter().position(|id| *id == task_id) {
+                expanded.remove(index);
+            } else {
+                expanded.push(task_id);
+            }
+        });
+    };
+
+    let add_subtask = move |parent_id: i64, title: String| {
+        spawn_local(async move {
+            let response =
+                Request::post(&format!("{}/tasks?view=changes&{}", SERVER, list_query()))
                     .credentials(web_sys::RequestCredentials::Include)
                     .json(&ResponseTask {
-                        task_id: task_id,
-                        task_title: "".to_string(),
-                        task_description: "".to_string(),
+                        parent_id: Some(parent_id),
+                        task_title: title,
+                        ..Default::default()
                     })
                     .unwrap()
                     .send()
                     .await
-                    .unwrap()
-                    .json()
-                    .await
                     .unwrap();
+            let changes = task_changes(response).await;
+            set_form_errors(&format!("subtask:{}", parent_id), changes.as_ref().err());
+            apply_changes(changes);
+            reload_lists();
+        })
+    };
 
-                set_data.set(fetched_response);
+    // Moves a subtask one place up (-1) or down (1) among its siblings.
+    let shift_subtask = move |parent_id: i64, task_id: i64, delta: isize| {
+        let mut children: Vec<i64> = subtasks(parent_id)
+            .iter()
+            .map(|task| task.task_id)
+            .collect();
+        let Some(index) = children.iter().position(|id| *id == task_id) else {
+            return;
+        };
+        let target = index as isize + delta;
+        if target < 0 || target as usize >= children.len() {
+            return;
+        }
+        children.swap(index, target as usize);
+        spawn_local(async move {
+            let response = Request::put(&format!(
+                "{}/task/children?view=changes&{}",
+                SERVER,
+                list_query()
+            ))
+            .credentials(web_sys::RequestCredentials::Include)
+            .json(&ChildrenOrder {
+                task_id: parent_id,
+                children,
             })
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+
+            apply_changes(task_changes(response).await);
+        })
+    };
+
+    let (dragged_task, set_dragged_task) = create_signal(None::<i64>);
+
+    // Drops a task card onto another one: a card dragged downwards lands after
+    // the target, one dragged upwards before it.
+    let drop_task = move |task_id: i64, target_id: i64| {
+        if task_id == target_id {
+            return;
         }
+        let tasks = data.get_untracked().tasks;
+        let index_of = |id: i64| tasks.iter().position(|task| task.task_id == id);
+        let (Some(index), Some(target_index)) = (index_of(task_id), index_of(target_id)) else {
+            return;
+        };
+        let position_info = if index < target_index {
+            PositionInfo {
+                task_id,
+                before_id: None,
+                after_id: Some(target_id),
+            }
+        } else {
+            PositionInfo {
+                task_id,
+                before_id: Some(target_id),
+                after_id: None,
+            }
+        };
+        spawn_local(async move {
+            let response = Request::put(&format!(
+                "{}/task/position?view=changes&{}",
+                SERVER,
+                list_query()
+            ))
+            .credentials(web_sys::RequestCredentials::Include)
+            .json(&position_info)
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+
+            apply_changes(task_changes(response).await);
+        })
+    };
+
+    let (hide_completed, set_hide_completed) = create_signal(false);
+
+    let (search_terms, set_search_terms) = create_signal("".to_string());
+    let (search_results, set_search_results) = create_signal(Vec::<SearchHit>::new());
+
+    let on_search_input = move |ev: leptos::ev::Event| {
+        let terms = event_target_value(&ev);
+        set_search_terms.set(terms.clone());
+        if terms.trim().is_empty() {
+            set_search_results.set(vec![]);
+            return;
+        }
+        spawn_local(async move {
+            let q: String = js_sys::encode_uri_component(&terms).into();
+            let response = Request::get(&format!("{}/tasks/search?q={}", SERVER, q))
+                .credentials(web_sys::RequestCredentials::Include)
+                .send()
+                .await
+                .unwrap();
+            // answers to earlier keystrokes may arrive late
+            if !response.ok() || search_terms.get_untracked() != terms {
+                return;
+            }
+            let fetched_response: SearchResponse = response.json().await.unwrap();
+            set_search_results.set(fetched_response.results);
+        })
+    };
+
+    // Shows a search hit in its list, with its parent expanded for subtasks.
+    let open_search_hit = move |hit: SearchHit| {
+        set_search_terms.set("".to_string());
+        set_search_results.set(vec![]);
+        set_tag_filter.set(vec![]);
+        set_selected_list.set(Some(hit.list_id));
+        if let Some(parent_id) = hit.parent_id {
+            set_expanded_tasks.update(|expanded| {
+                if !expanded.contains(&parent_id) {
+                    expanded.push(parent_id);
+                }
+            });
+        }
+        reload_list();
+    };
+
+    let on_sort_change = move |ev: leptos::ev::Event| {
+        let value = event_target_value(&ev);
+        let (key, order) = value.split_once(':').unwrap_or((&value, ""));
+        set_sort_key.set(key.to_string());
+        set_sort_order.set(order.to_string());
+        reload_list();
+    };
+
+    let on_tag_filter_click = move |ev: MouseEvent| {
+        ev.prevent_default();
+        let name = event_target_value(&ev);
+        set_tag_filter.update(|filter| {
+            if let Some(index) = filter.iter().position(|tag| *tag == name) {
+                filter.remove(index);
+            } else {
+                filter.push(name);
+            }
+        });
+        reload_list();
+    };
+
+    let on_list_click = move |ev: MouseEvent| {
+        ev.prevent_default();
+        set_selected_list.set(event_target_value(&ev).parse().ok());
+        reload_list();
+    };
+
+    let (new_list_name, set_new_list_name) = create_signal("".to_string());
+
+    let on_new_list_submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        spawn_local(async move {
+            let response = Request::post(&format!("{}/lists", SERVER))
+                .credentials(web_sys::RequestCredentials::Include)
+                .json(&ResponseList {
+                    name: new_list_name.get(),
+                    ..Default::default()
+                })
+                .unwrap()
+                .send()
+                .await
+                .unwrap();
+            if !response.ok() {
+                let error = api_error(response).await;
+                set_form_errors("list", Some(&error));
+                report_error(error);
+                return;
+            }
+            set_form_errors("list", None);
+            let fetched_response: ListResponse = response.json().await.unwrap();
+            set_new_list_name.set("".to_string());
+            set_lists.set(fetched_response.lists);
+        })
+    };
+
+    let (new_app_password_name, set_new_app_password_name) = create_signal("".to_string());
+
+    let on_new_app_password_submit = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        spawn_local(async move {
+            let response = Request::post(&format!("{}/app-passwords", SERVER))
+                .credentials(web_sys::RequestCredentials::Include)
+                .json(&AppPasswordInfo {
+                    name: new_app_password_name.get(),
+                })
+                .unwrap()
+                .send()
+                .await
+                .unwrap();
+            if !response.ok() {
+                let error = api_error(response).await;
+                set_form_errors("app_password", Some(&error));
+                report_error(error);
+                return;
+            }
+            set_form_errors("app_password", None);
+            set_new_app_password_name.set("".to_string());
+            set_app_passwords.set(response.json().await.unwrap());
+        })
+    };
+
+    let (import_file, set_import_file) = create_signal(None::<web_sys::File>);
+    let (import_report, set_import_report) = create_signal(None::<ImportResponse>);
+
+    let on_import_file_change = move |ev: leptos::ev::Event| {
+        let input: web_sys::HtmlInputElement = event_target(&ev);
+        set_import_file.set(input.files().and_then(|files| files.get(0)));
+        set_import_report.set(None);
+    };
+
+    // Sends the chosen export back, either only to see what it would do
+    // (`dry_run`) or to import it.
+    let import_tasks = move |dry_run: bool| {
+        let Some(file) = import_file.get() else {
+            return;
+        };
+        let format = if file.name().to_lowercase().ends_with(".csv") {
+            "csv"
+        } else {
+            "json"
+        };
+        spawn_local(async move {
+            let response = Request::post(&format!(
+                "{}/import?format={}&dry_run={}",
+                SERVER, format, dry_run
+            ))
+            .credentials(web_sys::RequestCredentials::Include)
+            .body(JsValue::from(file))
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+            if !response.ok() {
+                set_import_report.set(None);
+                report_error(api_error(response).await);
+                return;
+            }
+            let report: ImportResponse = response.json().await.unwrap();
+            if report.imported {
+                set_reload_needed.set(true);
+            }
+            set_import_report.set(Some(report));
+        })
+    };
+
+    let on_app_password_delete_click = move |ev: MouseEvent| {
+        ev.prevent_default();
+        let app_password_id: i64 = event_target_value(&ev).parse().unwrap();
+        spawn_local(async move {
+            let response =
+                Request::delete(&format!("{}/app-passwords/{}", SERVER, app_password_id))
+                    .credentials(web_sys::RequestCredentials::Include)
+                    .send()
+                    .await
+                    .unwrap();
+            if response.ok() {
+                set_app_passwords.set(response.json().await.unwrap());
+            } else {
+                report_error(api_error(response).await);
+            }
+        })
+    };
+
+    let on_list_delete_click = move |ev: MouseEvent| {
+        ev.prevent_default();
+        let list_id: i64 = event_target_value(&ev).parse().unwrap();
+        spawn_local(async move {
+            let response = Request::delete(&format!("{}/lists/{}", SERVER, list_id))
+                .credentials(web_sys::RequestCredentials::Include)
+                .send()
+                .await
+                .unwrap();
+            if !response.ok() {
+                report_error(api_error(response).await);
+                return;
+            }
+            let fetched_response: ListResponse = response.json().await.unwrap();
+            if selected_list.get_untracked() == Some(list_id) {
+                set_selected_list.set(None);
+            }
+            set_lists.set(fetched_response.lists);
+            reload_list();
+        })
+    };
+
+    let on_tag_delete_click = move |ev: MouseEvent| {
+        ev.prevent_default();
+        let tag_id: i64 = event_target_value(&ev).parse().unwrap();
+        spawn_local(async move {
+            let response = Request::delete(&format!("{}/tags/{}", SERVER, tag_id))
+                .credentials(web_sys::RequestCredentials::Include)
+                .send()
+                .await
+                .unwrap();
+            if !response.ok() {
+                report_error(api_error(response).await);
+                return;
+            }
+            let fetched_response: TagResponse = response.json().await.unwrap();
+            let names: Vec<String> = fetched_response
+                .tags
+                .iter()
+                .map(|tag| tag.name.clone())
+                .collect();
+            set_tag_filter.update(|filter| filter.retain(|name| names.contains(name)));
+            set_tags.set(fetched_response.tags);
+            reload_list();
+        })
     };
 
     view! {
@@ -217,36 +1240,318 @@ fn App() -> impl IntoView {
                     <div>
                         <input placeholder="Username" class="p-2 m-2" type="text" on:input=move |ev| {
                             set_username.set(event_target_value(&ev)) } prop:value=move||username.get() />
+                        <div class="text-danger small mx-2">{move || field_error("login", &["username"])}</div>
                     </div>
                     <div>
                         <input placeholder="Password" class="p-2 m-2" type="text" on:input=move |ev| {
                             set_password.set(event_target_value(&ev)) } prop:value=move|| password.get() />
+                        <div class="text-danger small mx-2">{move || field_error("login", &["password"])}</div>
                     </div>
                     <div class="d-flex flex-row justify-content-end">
                         <input class="btn btn-light m-2 p-2" type="submit" value="Sign in" />
                         <input class="btn btn-light m-2 p-2" type="reset" value="Clear" />
                     </div>
                 </form>
+
+                <form class="d-flex flex-column form border-top" on:submit=on_register_info_submit>
+                    <div>
+                        <input placeholder="New username" class="p-2 m-2" type="text" on:input=move |ev| {
+                            set_new_username.set(event_target_value(&ev)) } prop:value=move|| new_username.get() />
+                        <div class="text-danger small mx-2">{move || field_error("register", &["username"])}</div>
+                    </div>
+                    <div>
+                        <input placeholder="New password" class="p-2 m-2" type="password" on:input=move |ev| {
+                            set_new_password.set(event_target_value(&ev)) } prop:value=move|| new_password.get() />
+                        <div class="text-danger small mx-2">{move || field_error("register", &["password"])}</div>
+                    </div>
+                    <div class="d-flex flex-row justify-content-end">
+                        <input class="btn btn-light m-2 p-2" type="submit" value="Sign up" />
+                    </div>
+                </form>
+
+                <div class="d-flex flex-column border-top">
+                    <div class="m-2 p-1">"Lists"</div>
+                    <div class="list-group m-2">
+                        <button class=move || if selected_list.get().is_none() {
+                            "list-group-item list-group-item-action active" } else {
+                            "list-group-item list-group-item-action" } prop:value="" on:click=on_list_click>"All tasks"</button>
+                        <For each=move || lists.get() key=|list| (list.list_id, list.name.clone(), list.open_tasks)
+                            children=move |list: ResponseList| { view! {
+                            <div class="d-flex flex-row">
+                                <button class=move || if selected_list.get() == Some(list.list_id) {
+                                    "list-group-item list-group-item-action active d-flex justify-content-between" } else {
+                                    "list-group-item list-group-item-action d-flex justify-content-between" }
+                                    prop:value=list.list_id on:click=on_list_click>
+                                    {list.name.clone()}
+                                    <span class="badge bg-secondary rounded-pill">{list.open_tasks}</span>
+                                </button>
+                                <button class="btn btn-light" title="Delete list and its tasks" prop:value=list.list_id
+                                    on:click=on_list_delete_click>"×"</button>
+                            </div>
+                        }}
+                        />
+                    </div>
+                    <form class="d-flex flex-row form" on:submit=on_new_list_submit>
+                        <input placeholder="New list" class="p-2 m-2" type="text" on:input=move |ev| {
+                            set_new_list_name.set(event_target_value(&ev)) } prop:value=move || new_list_name.get() />
+                        <input class="btn btn-light m-2 p-2" type="submit" value="Add" />
+                    </form>
+                    <div class="text-danger small mx-2">{move || field_error("list", &["name"])}</div>
+                </div>
+
+                <div class="d-flex flex-column border-top">
+                    <div class="m-2 p-1">"Calendar feed"</div>
+                    <Show when=move || feed.get().url.is_some()
+                        fallback=|| view! { <div class="small mx-2">"Off"</div> }>
+                        <input class="p-1 m-2 small" type="text" readonly title="Subscribe to this URL in a calendar app"
+                            prop:value=move || feed.get().url.unwrap_or_default() />
+                    </Show>
+                    <div class="d-flex flex-row justify-content-end">
+                        <button class="btn btn-light m-2 p-2" title="Anyone with the old URL loses access"
+                            on:click=move |_| set_feed_enabled(true)>{move || if feed.get().url.is_some() {
+                            "New link" } else { "Turn on" }}</button>
+                        <Show when=move || feed.get().url.is_some()>
+                            <button class="btn btn-light m-2 p-2" on:click=move |_| set_feed_enabled(false)>"Turn off"</button>
+                        </Show>
+                    </div>
+                </div>
+
+                <div class="d-flex flex-column border-top">
+                    <div class="m-2 p-1">"CalDAV sync"</div>
+                    <input class="p-1 m-2 small" type="text" readonly title="Add this account in a CalDAV app"
+                        prop:value=format!("{}/dav/", SERVER) />
+                    <For each=move || app_passwords.get().app_passwords key=|app_password| app_password.app_password_id
+                        children=move |app_password: ResponseAppPassword| { view! {
+                        <div class="d-flex flex-row justify-content-between align-items-center small mx-2">
+                            <span title=format!("Created {}", app_password.created_at)>{app_password.name.clone()}</span>
+                            <button class="btn btn-light" title="Revoke app password" prop:value=app_password.app_password_id
+                                on:click=on_app_password_delete_click>"×"</button>
+                        </div>
+                    }}
+                    />
+                    <Show when=move || app_passwords.get().password.is_some()>
+                        <div class="small mx-2">"New password, shown only once:"</div>
+                        <input class="p-1 m-2 small" type="text" readonly
+                            prop:value=move || app_passwords.get().password.unwrap_or_default() />
+                    </Show>
+                    <form class="d-flex flex-row form" on:submit=on_new_app_password_submit>
+                        <input placeholder="New app password" class="p-2 m-2" type="text" on:input=move |ev| {
+                            set_new_app_password_name.set(event_target_value(&ev)) }
+                            prop:value=move || new_app_password_name.get() />
+                        <input class="btn btn-light m-2 p-2" type="submit" value="Add" />
+                    </form>
+                    <div class="text-danger small mx-2">{move || field_error("app_password", &["name"])}</div>
+                </div>
+
+                <div class="d-flex flex-column border-top">
+                    <div class="m-2 p-1">"Export and import"</div>
+                    <div class="d-flex flex-row">
+                        <a class="btn btn-light m-2 p-2" download href=format!("{}/export?format=json", SERVER)>"JSON"</a>
+                        <a class="btn btn-light m-2 p-2" download href=format!("{}/export?format=csv", SERVER)>"CSV"</a>
+                    </div>
+                    <input class="p-1 m-2 small" type="file" accept=".json,.csv,application/json,text/csv"
+                        on:change=on_import_file_change />
+                    <div class="d-flex flex-row justify-content-end">
+                        <button class="btn btn-light m-2 p-2" disabled=move || import_file.get().is_none()
+                            title="Shows what importing would do" on:click=move |_| import_tasks(true)>"Check"</button>
+                        <button class="btn btn-light m-2 p-2" disabled=move || import_file.get().is_none()
+                            on:click=move |_| import_tasks(false)>"Import"</button>
+                    </div>
+                    {move || import_report.get().map(|report| {
+                        let summary = match (report.imported, report.errors.is_empty()) {
+                            (true, _) => format!("Imported {} tasks", report.created),
+                            (false, true) => format!("{} tasks would be imported", report.created),
+                            (false, false) => "Nothing was imported".to_string(),
+                        };
+                        view! {
+                            <div class="small mx-2">
+                                <div>{summary}</div>
+                                {(!report.lists_created.is_empty()).then(|| view! {
+                                    <div>{format!("New lists: {}", report.lists_created.join(", "))}</div>
+                                })}
+                                {report.duplicates.iter().map(|issue| view! {
+                                    <div class="text-muted">{format!("Row {}: skipped, {}", issue.row, issue.message)}</div>
+                                }).collect_view()}
+                                {report.errors.iter().map(|issue| view! {
+                                    <div class="text-danger">{format!("Row {} ({}): {}", issue.row, issue.title, issue.message)}</div>
+                                }).collect_view()}
+                            </div>
+                        }
+                    })}
+                </div>
             </div>
             <div class="d-flex flex-column flex-fill justify-content-top align-items-center flex-fill">
                 <div class="h1 d-flex flex-row m-2 p-2"><u>"Your To Dos"</u></div>
-                <For each=move || data.get().tasks key=|task| task.task_id children=move | task:ResponseTask| { view! {
-                    <form class="d-flex flex-column form bg-light rounded p-2 m-2">
+                <input class="form-control m-2 w-50" type="search" placeholder="Search tasks"
+                    on:input=on_search_input prop:value=move || search_terms.get() />
+                <ul class="list-group m-2 w-50">
+                    <For each=move || search_results.get() key=|hit| hit.task_id children=move |hit: SearchHit| {
+                        let clicked_hit = hit.clone();
+                        view! {
+                        <li class="list-group-item list-group-item-action"
+                            on:click=move |_| open_search_hit(clicked_hit.clone())>
+                            <div class=if hit.done { "text-decoration-line-through" } else { "" }
+                                inner_html=hit.title.clone()></div>
+                            <small class="text-muted" inner_html=hit.snippet.clone()></small>
+                        </li>
+                    }}
+                    />
+                </ul>
+                <div class="form-check">
+                    <input class="form-check-input" type="checkbox" id="hide-completed" on:change=move |ev| {
+                        set_hide_completed.set(event_target_checked(&ev)) } prop:checked=move || hide_completed.get() />
+                    <label class="form-check-label" for="hide-completed">"Hide completed"</label>
+                </div>
+                <select class="form-select m-2" on:change=on_sort_change>
+                    <option value="manual" selected>"My order"</option>
+                    <option value="priority">"Priority"</option>
+                    <option value="due_date">"Due date"</option>
+                    <option value="created:desc">"Newest first"</option>
+                    <option value="created:asc">"Oldest first"</option>
+                    <option value="title">"Title"</option>
+                </select>
+                <div class="d-flex flex-row flex-wrap justify-content-center">
+                    <For each=move || tags.get() key=|tag| (tag.tag_id, tag.name.clone()) children=move |tag: ResponseTag| {
+                        let name = tag.name.clone();
+                        view! {
+                        <div class="btn-group m-1">
+                            <button class=move || if tag_filter.get().contains(&name) {
+                                "btn btn-sm btn-primary rounded-start-pill" } else {
+                                "btn btn-sm btn-outline-secondary rounded-start-pill" }
+                                prop:value=tag.name.clone() on:click=on_tag_filter_click>{format!("#{}", tag.name)}</button>
+                            <button class="btn btn-sm btn-outline-secondary rounded-end-pill" title="Delete tag"
+                                prop:value=tag.tag_id on:click=on_tag_delete_click>"×"</button>
+                        </div>
+                    }}
+                    />
+                </div>
+                <For each=move || {
+                        // subtasks are shown under their parent, unless the parent is filtered out
+                        let tasks = data.get().tasks;
+                        let task_ids: Vec<i64> = tasks.iter().map(|task| task.task_id).collect();
+                        tasks.into_iter().filter(move |task| !(hide_completed.get() && task.done)
+                            && task.parent_id.is_none_or(|parent_id| !task_ids.contains(&parent_id)))
+                    }
+                    // a recurring task moves to its next occurrence without changing `done`
+                    key=|task| (task.task_id, task.done, task.due_at.clone(), task.start_at.clone(), task.recurrence.clone())
+                    children=move | task:ResponseTask| {
+                    let (new_subtask_title, set_new_subtask_title) = create_signal("".to_string());
+                    let recurrence = task.recurrence.clone().unwrap_or_default();
+                    let recurrence_preset_value = recurrence_preset(&recurrence);
+                    let recurring = task.recurrence.is_some();
+                    view! {
+                    <form class=move || if data.get().groups.overdue.contains(&task.task_id) {
+                        "d-flex flex-column form bg-light rounded p-2 m-2 border border-danger" } else {
+                        "d-flex flex-column form bg-light rounded p-2 m-2" }
+                        // cards can only be rearranged while the list is in manual order
+                        draggable=move || if sort_key.get() == "manual" { "true" } else { "false" }
+                        on:dragstart=move |ev: ev::DragEvent| {
+                            if let Some(data_transfer) = ev.data_transfer() {
+                                let _ = data_transfer.set_data("text/plain", &task.task_id.to_string());
+                            }
+                            set_dragged_task.set(Some(task.task_id));
+                        }
+                        on:dragover=move |ev: ev::DragEvent| ev.prevent_default()
+                        on:drop=move |ev: ev::DragEvent| {
+                            ev.prevent_default();
+                            if let Some(dragged) = dragged_task.get_untracked() {
+                                drop_task(dragged, task.task_id);
+                            }
+                            set_dragged_task.set(None);
+                        }
+                        on:dragend=move |_| set_dragged_task.set(None)>
                     //<div>{task.task_id}</div>
+                    <div class="form-check m-2" title=task.completed_at.clone().map(|completed_at| format!("Completed {}", completed_at))>
+                        <input class="form-check-input" type="checkbox" value={task.task_id} prop:checked=task.done
+                            on:change=on_task_done_change />
+                        <label class="form-check-label">{if task.done { "Completed" } else { "Open" }}</label>
+                    </div>
 
                     <div>
                         <input class="text text-center p-2 m-2" type="text" on:input=move |ev| {
                             set_selected_task_title.set(event_target_value(&ev)) } disabled=move|| selected_task_id.get()
                             !=task.task_id prop:value={ if selected_task_id.get() !=task.task_id { task.task_title } else {
                             selected_task_title.get() }} />
+                        <div class="text-danger small mx-2">{move || field_error(&task.task_id.to_string(), &["task_title"])}</div>
                     </div>
                     <div>
                         <input class="text text-center p-2 m-2" type="text" on:input=move |ev| {
                             set_selected_task_description.set(event_target_value(&ev)) } disabled=move||
                             selected_task_id.get() !=task.task_id prop:value=if selected_task_id.get() !=task.task_id {
                             task.task_description } else { selected_task_description.get() } />
+                        <div class="text-danger small mx-2">{move || field_error(&task.task_id.to_string(), &["task_description"])}</div>
+                    </div>
+                    <div class="d-flex flex-row">
+                        <label class="m-2">"Start"
+                            <input class="p-1 m-1" type="datetime-local" on:input=move |ev| {
+                                set_selected_task_start_at.set(event_target_value(&ev)) } disabled=move||
+                                selected_task_id.get() !=task.task_id prop:value=if selected_task_id.get() !=task.task_id {
+                                rfc3339_to_local_input(&task.start_at) } else { selected_task_start_at.get() } />
+                        </label>
+                        <label class=move || if data.get().groups.overdue.contains(&task.task_id) {
+                            "m-2 text-danger" } else { "m-2" }>"Due"
+                            <input class="p-1 m-1" type="datetime-local" on:input=move |ev| {
+                                set_selected_task_due_at.set(event_target_value(&ev)) } disabled=move||
+                                selected_task_id.get() !=task.task_id prop:value=if selected_task_id.get() !=task.task_id {
+                                rfc3339_to_local_input(&task.due_at) } else { selected_task_due_at.get() } />
+                        </label>
+                        <label class="m-2">"Priority"
+                            <select class="p-1 m-1" on:change=move |ev| {
+                                set_selected_task_priority.set(event_target_value(&ev).parse().unwrap()) } disabled=move||
+                                selected_task_id.get() !=task.task_id prop:value=move || if selected_task_id.get() !=task.task_id {
+                                task.priority.to_string() } else { selected_task_priority.get().to_string() }>
+                                <PriorityOptions />
+                            </select>
+                        </label>
+                        <label class="m-2">"List"
+                            <select class="p-1 m-1" on:change=move |ev| {
+                                move_task(task.task_id, event_target_value(&ev).parse().unwrap()) }
+                                prop:value=move || task.list_id.unwrap_or_default().to_string()>
+                                <For each=move || lists.get() key=|list| (list.list_id, list.name.clone())
+                                    children=move |list: ResponseList| { view! {
+                                    <option value=list.list_id selected=Some(list.list_id) == task.list_id>
+                                        {list.name}</option>
+                                }}
+                                />
+                            </select>
+                        </label>
+                    </div>
+                    <div class="text-danger small mx-2">{move || field_error(&task.task_id.to_string(), &["start_at", "due_at", "priority"])}</div>
+                    <div class="d-flex flex-row align-items-center">
+                        <label class="m-2">"Repeat"
+                            <select class="p-1 m-1" on:change=move |ev| {
+                                set_selected_task_recurrence.set(recurrence_preset_rule(&event_target_value(&ev), &selected_task_recurrence.get())) }
+                                disabled=move|| selected_task_id.get() !=task.task_id prop:value=move || if selected_task_id.get() !=task.task_id {
+                                recurrence_preset_value.clone() } else {
+                                recurrence_preset(&selected_task_recurrence.get()) }>
+                                <RecurrenceOptions />
+                            </select>
+                        </label>
+                        <input class="p-1 m-1 flex-fill" type="text" placeholder="RRULE, e.g. FREQ=WEEKLY;BYDAY=MO" on:input=move |ev| {
+                            set_selected_task_recurrence.set(event_target_value(&ev)) } disabled=move||
+                            selected_task_id.get() !=task.task_id prop:value=move || if selected_task_id.get() !=task.task_id {
+                            recurrence.clone() } else { selected_task_recurrence.get() } />
+                    </div>
+                    <div class="text-danger small mx-2">{move || field_error(&task.task_id.to_string(), &["recurrence"])}</div>
+                    <div class="d-flex flex-row flex-wrap align-items-center m-2">
+                        {move || if selected_task_id.get() != task.task_id {
+                            task.tags.iter().map(|name| view! {
+                                <span class="badge rounded-pill bg-secondary m-1">{format!("#{}", name)}</span>
+                            }).collect_view()
+                        } else {
+                            view! {
+                                <input class="p-1" type="text" placeholder="Tags, comma separated" on:input=move |ev| {
+                                    set_selected_task_tags.set(event_target_value(&ev)) }
+                                    prop:value=move || selected_task_tags.get() />
+                            }.into_view()
+                        }}
+                        <div class="text-danger small mx-2">{move || field_error(&task.task_id.to_string(), &["tags"])}</div>
                     </div>
                     <div class="d-flex flex-row justify-content-end">
+                        <Show when=move || recurring && !task.done && selected_task_id.get() != task.task_id>
+                            <button class="btn btn-light m-2 p-2" type="button" title="Move on to the next occurrence"
+                                on:click=move |ev| { ev.prevent_default(); skip_occurrence(task.task_id) }>"Skip"</button>
+                        </Show>
                         <button class="btn btn-light m-2 p-2" value={task.task_id} prop:value=move || task.task_id
                             on:click=on_task_edit_click>{move|| if
                             selected_task_id.get() != task.task_id{"Edit"} else {"Done"}}</button>
@@ -254,6 +1559,44 @@ fn App() -> impl IntoView {
                             task.task_id>{move|| if
                             selected_task_id.get() != task.task_id{"Delete"} else {"Cancel"}}</button>
                     </div>
+                    <div class="d-flex flex-column m-2">
+                        <button class="btn btn-sm btn-link align-self-start" type="button" prop:value=task.task_id
+                            on:click=on_subtasks_toggle>{move || {
+                            let subtasks = subtasks(task.task_id);
+                            let done = subtasks.iter().filter(|task| task.done).count();
+                            format!("Subtasks {}/{}", done, subtasks.len())
+                        }}</button>
+                        <Show when=move || expanded_tasks.get().contains(&task.task_id)>
+                            <ul class="list-group">
+                                <For each=move || subtasks(task.task_id) key=|subtask| (subtask.task_id, subtask.done, subtask.position)
+                                    children=move |subtask: ResponseTask| { view! {
+                                    <li class="list-group-item d-flex flex-row align-items-center">
+                                        <input class="form-check-input m-1" type="checkbox" value={subtask.task_id}
+                                            prop:checked=subtask.done on:change=on_task_done_change />
+                                        <span class=if subtask.done { "flex-fill m-1 text-decoration-line-through" } else {
+                                            "flex-fill m-1" }>{subtask.task_title.clone()}</span>
+                                        <button class="btn btn-sm btn-light" type="button" title="Move up" on:click=move |ev| {
+                                            ev.prevent_default(); shift_subtask(task.task_id, subtask.task_id, -1) }>"↑"</button>
+                                        <button class="btn btn-sm btn-light" type="button" title="Move down" on:click=move |ev| {
+                                            ev.prevent_default(); shift_subtask(task.task_id, subtask.task_id, 1) }>"↓"</button>
+                                        <button class="btn btn-sm btn-light" type="button" title="Remove subtask" on:click=move |ev| {
+                                            ev.prevent_default(); delete_task(subtask.task_id) }>"×"</button>
+                                    </li>
+                                }}
+                                />
+                            </ul>
+                            <div class="d-flex flex-row">
+                                <input class="p-1 m-1 flex-fill" type="text" placeholder="New subtask" on:input=move |ev| {
+                                    set_new_subtask_title.set(event_target_value(&ev)) } prop:value=move || new_subtask_title.get() />
+                                <button class="btn btn-sm btn-light m-1" type="button" on:click=move |ev| {
+                                    ev.prevent_default();
+                                    add_subtask(task.task_id, new_subtask_title.get());
+                                    set_new_subtask_title.set("".to_string());
+                                }>"Add"</button>
+                            </div>
+                            <div class="text-danger small mx-2">{move || field_error(&format!("subtask:{}", task.task_id), &["task_title"])}</div>
+                        </Show>
+                    </div>
                     </form>
                     }
                     }
@@ -264,12 +1607,60 @@ fn App() -> impl IntoView {
                             <input class="text text-center p-2 m-2" type="text" disabled=move|| selected_task_id.get()!=-1
                                 on:input=move |ev| { set_selected_task_title.set(event_target_value(&ev)) } prop:value=move
                                 || if selected_task_id.get() !=1 { "" .to_string() } else { selected_task_title.get() } />
+                            <div class="text-danger small mx-2">{move || field_error("new_task", &["task_title"])}</div>
                         </div>
                         <div>
                             <input class="text text-center p-2 m-2" type="text" disabled=move|| selected_task_id.get()!=-1
                                 on:input=move |ev| { set_selected_task_description.set(event_target_value(&ev)) }
                                 prop:value=move ||if selected_task_id.get() !=1 { "" .to_string() } else {
                                 selected_task_description.get() } />
+                            <div class="text-danger small mx-2">{move || field_error("new_task", &["task_description"])}</div>
+                        </div>
+                        <div class="d-flex flex-row">
+                            <label class="m-2">"Start"
+                                <input class="p-1 m-1" type="datetime-local" disabled=move|| selected_task_id.get()!=-1
+                                    on:input=move |ev| { set_selected_task_start_at.set(event_target_value(&ev)) }
+                                    prop:value=move ||if selected_task_id.get() !=1 { "" .to_string() } else {
+                                    selected_task_start_at.get() } />
+                            </label>
+                            <label class="m-2">"Due"
+                                <input class="p-1 m-1" type="datetime-local" disabled=move|| selected_task_id.get()!=-1
+                                    on:input=move |ev| { set_selected_task_due_at.set(event_target_value(&ev)) }
+                                    prop:value=move ||if selected_task_id.get() !=1 { "" .to_string() } else {
+                                    selected_task_due_at.get() } />
+                            </label>
+                            <label class="m-2">"Priority"
+                                <select class="p-1 m-1" disabled=move|| selected_task_id.get()!=-1
+                                    on:change=move |ev| { set_selected_task_priority.set(event_target_value(&ev).parse().unwrap()) }
+                                    prop:value=move || if selected_task_id.get() !=1 { "0".to_string() } else {
+                                    selected_task_priority.get().to_string() }>
+                                    <PriorityOptions />
+                                </select>
+                            </label>
+                        </div>
+                        <div class="text-danger small mx-2">{move || field_error("new_task", &["start_at", "due_at", "priority"])}</div>
+                        <div class="d-flex flex-row align-items-center">
+                            <label class="m-2">"Repeat"
+                                <select class="p-1 m-1" disabled=move|| selected_task_id.get()!=-1
+                                    on:change=move |ev| { set_selected_task_recurrence.set(recurrence_preset_rule(&event_target_value(&ev), &selected_task_recurrence.get())) }
+                                    prop:value=move || recurrence_preset(&selected_task_recurrence.get())>
+                                    <RecurrenceOptions />
+                                </select>
+                            </label>
+                            <input class="p-1 m-1 flex-fill" type="text" placeholder="RRULE, e.g. FREQ=WEEKLY;BYDAY=MO"
+                                disabled=move|| selected_task_id.get()!=-1
+                                on:input=move |ev| { set_selected_task_recurrence.set(event_target_value(&ev)) }
+                                prop:value=move || if selected_task_id.get() != -1 { "".to_string() } else {
+                                selected_task_recurrence.get() } />
+                        </div>
+                        <div class="text-danger small mx-2">{move || field_error("new_task", &["recurrence"])}</div>
+                        <div>
+                            <input class="text text-center p-2 m-2" type="text" placeholder="Tags, comma separated"
+                                disabled=move|| selected_task_id.get()!=-1
+                                on:input=move |ev| { set_selected_task_tags.set(event_target_value(&ev)) }
+                                prop:value=move ||if selected_task_id.get() !=1 { "" .to_string() } else {
+                                selected_task_tags.get() } />
+                            <div class="text-danger small mx-2">{move || field_error("new_task", &["tags"])}</div>
                         </div>
                         <div class="d-flex flex-row justify-content-end">
                             <button class="btn btn-light m-2 p-2" disabled=move|| selected_task_id.get()!=-1
@@ -284,3 +1675,52 @@ fn App() -> impl IntoView {
     </>
     }
 }
+
+// Common repetitions offered in the task forms; anything else is "custom" and
+// edited as a raw RRULE.
+const RECURRENCE_PRESETS: [(&str, &str); 6] = [
+    ("", "Never"),
+    ("FREQ=DAILY", "Daily"),
+    ("FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR", "Weekdays"),
+    ("FREQ=WEEKLY", "Weekly"),
+    ("FREQ=MONTHLY", "Monthly"),
+    ("FREQ=YEARLY", "Yearly"),
+];
+
+// The option matching a rule.
+fn recurrence_preset(rule: &str) -> String {
+    if RECURRENCE_PRESETS.iter().any(|(preset, _)| *preset == rule) {
+        rule.to_string()
+    } else {
+        "custom".to_string()
+    }
+}
+
+// The rule to edit after picking an option; "custom" keeps the current one.
+fn recurrence_preset_rule(option: &str, current: &str) -> String {
+    if option == "custom" {
+        current.to_string()
+    } else {
+        option.to_string()
+    }
+}
+
+#[component]
+fn RecurrenceOptions() -> impl IntoView {
+    view! {
+        {RECURRENCE_PRESETS.iter().map(|(rule, label)| view! {
+            <option value=*rule>{*label}</option>
+        }).collect_view()}
+        <option value="custom">"Custom"</option>
+    }
+}
+
+#[component]
+fn PriorityOptions() -> impl IntoView {
+    view! {
+        <option value="0">"None"</option>
+        <option value="1">"Low"</option>
+        <option value="2">"Medium"</option>
+        <option value="3">"High"</option>
+    }
+}
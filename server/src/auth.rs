@@ -0,0 +1,93 @@
+use std::future::{ready, Ready};
+
+use actix_session::{Session, SessionExt};
+use actix_web::{
+    dev::Payload, http::StatusCode, FromRequest, HttpRequest, HttpResponse, ResponseError,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error;
+
+const SESSION_KEY: &str = "session_id";
+
+// The logged in user, resolved from the session cookie. Handlers taking it
+// reject anonymous requests with a 401.
+#[derive(Serialize, Deserialize)]
+pub struct CurrentUser {
+    pub user_id: i64,
+    pub username: String,
+}
+
+// Like `CurrentUser`, but lets anonymous requests through as `None`.
+pub struct OptionalUser(pub Option<CurrentUser>);
+
+#[derive(Debug)]
+pub enum AuthError {
+    Unauthorized,
+    InvalidCredentials,
+    Session,
+}
+
+impl CurrentUser {
+    pub fn log_in(self, session: &Session) -> Result<(), AuthError> {
+        session.insert(SESSION_KEY, self).map_err(|err| {
+            println!("{err}");
+            AuthError::Session
+        })
+    }
+}
+
+fn session_user(req: &HttpRequest) -> Result<Option<CurrentUser>, AuthError> {
+    req.get_session()
+        .get::<CurrentUser>(SESSION_KEY)
+        .map_err(|err| {
+            println!("{err}");
+            AuthError::Session
+        })
+}
+
+impl FromRequest for CurrentUser {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(session_user(req).and_then(|user| user.ok_or(AuthError::Unauthorized)))
+    }
+}
+
+impl FromRequest for OptionalUser {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(session_user(req).map(OptionalUser))
+    }
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::Unauthorized => write!(f, "not logged in"),
+            AuthError::InvalidCredentials => write!(f, "unknown username or wrong password"),
+            AuthError::Session => write!(f, "the session could not be read"),
+        }
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::Unauthorized | AuthError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            AuthError::Session => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let code = match self {
+            AuthError::Unauthorized => "unauthorized",
+            AuthError::InvalidCredentials => "invalid_credentials",
+            AuthError::Session => "session_error",
+        };
+        error::error_response(self.status_code(), code, self.to_string(), vec![])
+    }
+}
@@ -0,0 +1,864 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    cookie::Key,
+    delete, get,
+    http::{
+        header::{self, HeaderValue},
+        Method, StatusCode,
+    },
+    post,
+    web::{self, Data, Json},
+    HttpRequest, HttpResponse, ResponseError, Result,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use roxmltree::{Document, Node};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use time::UtcOffset;
+
+use crate::auth::{AuthError, CurrentUser};
+use crate::db::{
+    AppPassword, DbError, List, Task, TaskEdit, TaskFilter, TaskSort, User, UserTasksDB,
+};
+use crate::error;
+use crate::ical;
+use crate::validate::{Charset, Rule};
+use crate::{InvalidInput, TaskInfo};
+
+const DAV: &str = "DAV:";
+const CALDAV: &str = "urn:ietf:params:xml:ns:caldav";
+const CALENDARSERVER: &str = "http://calendarserver.org/ns/";
+
+// The principal and calendar home of whoever is signed in.
+const HOME: &str = "/dav/";
+
+const ALLOWED_METHODS: &str = "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, REPORT";
+
+// What a path below /dav/ names.
+enum Target {
+    Home,
+    List(i64),
+    // a list and the name of a task's resource in it
+    Task(i64, String),
+}
+
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = value
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+// Accepts plain paths as well as full URLs, as found in multiget hrefs.
+fn target(path: &str) -> Option<Target> {
+    let path = &path[path.find("/dav")? + 4..];
+    let segments: Vec<String> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(decode)
+        .collect();
+    match &segments[..] {
+        [] => Some(Target::Home),
+        [list_id] => list_id.parse().ok().map(Target::List),
+        [list_id, name] => Some(Target::Task(list_id.parse().ok()?, name.clone())),
+        _ => None,
+    }
+}
+
+fn list_href(list_id: i64) -> String {
+    format!("{HOME}{list_id}/")
+}
+
+fn task_href(task: &Task) -> String {
+    format!(
+        "{}{}",
+        list_href(task.list_id),
+        encode(&ical::resource_name(task))
+    )
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Credentials of an `Authorization: Basic` header.
+fn basic_credentials(req: &HttpRequest) -> Option<(String, String)> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, encoded) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+// How long accepted credentials are taken again without hashing them.
+const CREDENTIALS_TTL: Duration = Duration::from_secs(300);
+
+struct Accepted {
+    user_id: i64,
+    app_password_id: Option<i64>,
+    at: Instant,
+}
+
+// DAV clients send Basic credentials with every request, and a sync takes many
+// of them, so credentials are remembered for a while once accepted. Only an
+// HMAC of them is kept, never the passwords themselves.
+pub struct AcceptedCredentials {
+    key: Vec<u8>,
+    credentials: Mutex<HashMap<[u8; 32], Accepted>>,
+}
+
+impl AcceptedCredentials {
+    pub fn new(key: &Key) -> Self {
+        AcceptedCredentials {
+            key: key.signing().to_vec(),
+            credentials: Mutex::default(),
+        }
+    }
+
+    fn digest(&self, (username, password): &(String, String)) -> [u8; 32] {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC takes any key length");
+        // the length keeps the split between username and password unambiguous
+        mac.update(&(username.len() as u64).to_be_bytes());
+        mac.update(username.as_bytes());
+        mac.update(password.as_bytes());
+        mac.finalize().into_bytes().into()
+    }
+
+    fn get(&self, credentials: &(String, String)) -> Option<i64> {
+        let digest = self.digest(credentials);
+        let accepted = self.credentials.lock().unwrap();
+        accepted
+            .get(&digest)
+            .filter(|accepted| accepted.at.elapsed() < CREDENTIALS_TTL)
+            .map(|accepted| accepted.user_id)
+    }
+
+    fn insert(&self, credentials: &(String, String), user_id: i64, app_password_id: Option<i64>) {
+        let digest = self.digest(credentials);
+        let mut accepted = self.credentials.lock().unwrap();
+        accepted.retain(|_, accepted| accepted.at.elapsed() < CREDENTIALS_TTL);
+        accepted.insert(
+            digest,
+            Accepted {
+                user_id,
+                app_password_id,
+                at: Instant::now(),
+            },
+        );
+    }
+
+    // Revoking an app password takes effect right away.
+    fn forget(&self, app_password_id: i64) {
+        let mut accepted = self.credentials.lock().unwrap();
+        accepted.retain(|_, accepted| accepted.app_password_id != Some(app_password_id));
+    }
+}
+
+// Either one of the user's app passwords or the account password. App
+// passwords are checked first, as they are found without hashing.
+async fn authenticate(
+    db: &UserTasksDB,
+    accepted: &AcceptedCredentials,
+    req: &HttpRequest,
+) -> Result<Option<User>, DbError> {
+    let Some(credentials) = basic_credentials(req) else {
+        return Ok(None);
+    };
+    if let Some(user_id) = accepted.get(&credentials) {
+        return db.get_user(user_id).await;
+    }
+    let (username, password) = credentials.clone();
+    if let Some((user, app_password_id)) = db
+        .get_user_by_app_password(username.clone(), password.clone())
+        .await?
+    {
+        accepted.insert(&credentials, user.user_id, Some(app_password_id));
+        return Ok(Some(user));
+    }
+    let mut users = db.get_user_by_credentials(username, password).await?;
+    if users.len() != 1 {
+        return Ok(None);
+    }
+    let user = users.pop();
+    if let Some(user) = &user {
+        accepted.insert(&credentials, user.user_id, None);
+    }
+    Ok(user)
+}
+
+fn challenge() -> HttpResponse {
+    let mut response = AuthError::Unauthorized.error_response();
+    response.headers_mut().insert(
+        header::WWW_AUTHENTICATE,
+        HeaderValue::from_static("Basic realm=\"rustodo\", charset=\"UTF-8\""),
+    );
+    response
+}
+
+// A DAV precondition failure, e.g. <c:no-uid-conflict/>.
+fn dav_error(status: StatusCode, condition: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type("application/xml; charset=utf-8")
+        .body(format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+            <d:error xmlns:d=\"{DAV}\" xmlns:c=\"{CALDAV}\">{condition}</d:error>"
+        ))
+}
+
+fn invalid_xml(err: roxmltree::Error) -> InvalidInput {
+    InvalidInput::field("body", format!("the request body is not valid XML: {err}"))
+}
+
+// Whether If-Match or If-None-Match rule the request out, given the current
+// ETag of the resource or `None` when it does not exist yet.
+fn precondition_failed(req: &HttpRequest, etag: Option<&str>) -> bool {
+    let matches = |name: header::HeaderName| {
+        let value = req.headers().get(name)?.to_str().ok()?;
+        Some(etag.is_some_and(|etag| {
+            value.trim() == "*"
+                || value
+                    .split(',')
+                    .any(|tag| tag.trim().trim_start_matches("W/") == etag)
+        }))
+    };
+    matches(header::IF_MATCH) == Some(false) || matches(header::IF_NONE_MATCH) == Some(true)
+}
+
+fn precondition_response() -> HttpResponse {
+    error::error_response(
+        StatusCode::PRECONDITION_FAILED,
+        "precondition_failed",
+        "the resource has changed".to_string(),
+        vec![],
+    )
+}
+
+// A resource as seen through PROPFIND and REPORT.
+enum Resource<'a> {
+    Home(&'a User),
+    List(&'a List, i64),
+    // a task and the other tasks of its list, which its parent is one of
+    Task(&'a Task, &'a [Task]),
+}
+
+const HOME_PROPS: [(&str, &str); 5] = [
+    (DAV, "resourcetype"),
+    (DAV, "displayname"),
+    (DAV, "current-user-principal"),
+    (DAV, "principal-URL"),
+    (CALDAV, "calendar-home-set"),
+];
+
+const LIST_PROPS: [(&str, &str); 7] = [
+    (DAV, "resourcetype"),
+    (DAV, "displayname"),
+    (DAV, "current-user-principal"),
+    (DAV, "current-user-privilege-set"),
+    (DAV, "supported-report-set"),
+    (CALDAV, "supported-calendar-component-set"),
+    (CALENDARSERVER, "getctag"),
+];
+
+const TASK_PROPS: [(&str, &str); 4] = [
+    (DAV, "resourcetype"),
+    (DAV, "current-user-principal"),
+    (DAV, "getetag"),
+    (DAV, "getcontenttype"),
+];
+
+impl Resource<'_> {
+    fn href(&self) -> String {
+        match self {
+            Resource::Home(_) => HOME.to_string(),
+            Resource::List(list, _) => list_href(list.list_id),
+            Resource::Task(task, _) => task_href(task),
+        }
+    }
+
+    // The properties sent when the client does not name any.
+    fn all_props(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Resource::Home(_) => &HOME_PROPS,
+            Resource::List(..) => &LIST_PROPS,
+            Resource::Task(..) => &TASK_PROPS,
+        }
+    }
+
+    // The property as an XML element, `None` if the resource has no such
+    // property.
+    fn prop(&self, namespace: &str, name: &str) -> Option<String> {
+        let value = match (self, namespace, name) {
+            (Resource::Home(_), DAV, "resourcetype") => "<d:collection/><d:principal/>".to_string(),
+            (Resource::List(..), DAV, "resourcetype") => "<d:collection/><c:calendar/>".to_string(),
+            (Resource::Task(..), DAV, "resourcetype") => String::new(),
+            (Resource::Home(user), DAV, "displayname") => escape(&user.username),
+            (Resource::List(list, _), DAV, "displayname") => escape(&list.name),
+            (_, DAV, "current-user-principal")
+            | (Resource::Home(_), DAV, "principal-URL")
+            | (Resource::Home(_), CALDAV, "calendar-home-set") => {
+                format!("<d:href>{HOME}</d:href>")
+            }
+            (Resource::List(..) | Resource::Task(..), DAV, "current-user-privilege-set") => {
+                ["read", "write", "write-content", "bind", "unbind"]
+                    .iter()
+                    .map(|privilege| format!("<d:privilege><d:{privilege}/></d:privilege>"))
+                    .collect()
+            }
+            (Resource::List(..), DAV, "supported-report-set") => {
+                ["calendar-query", "calendar-multiget"]
+                    .iter()
+                    .map(|report| {
+                        format!(
+                    "<d:supported-report><d:report><c:{report}/></d:report></d:supported-report>"
+                )
+                    })
+                    .collect()
+            }
+            (Resource::List(..), CALDAV, "supported-calendar-component-set") => {
+                "<c:comp name=\"VTODO\"/>".to_string()
+            }
+            // every change of the user's tasks, lists or tags bumps the revision
+            (Resource::List(_, revision), CALENDARSERVER, "getctag") => revision.to_string(),
+            (Resource::Task(task, tasks), DAV, "getetag") => escape(&ical::etag(task, tasks)),
+            (Resource::Task(..), DAV, "getcontenttype") => {
+                "text/calendar; charset=utf-8; component=VTODO".to_string()
+            }
+            (Resource::Task(task, tasks), CALDAV, "calendar-data") => {
+                escape(&ical::calendar(None, std::slice::from_ref(*task), tasks))
+            }
+            _ => return None,
+        };
+        let prefix = match namespace {
+            DAV => "d",
+            CALDAV => "c",
+            _ => "cs",
+        };
+        Some(format!("<{prefix}:{name}>{value}</{prefix}:{name}>"))
+    }
+}
+
+// The properties named in a PROPFIND or REPORT body as (namespace, name);
+// `None` asks for all of them, as an empty body or <allprop/> does.
+fn requested_props(document: Option<&Document>) -> Option<Vec<(String, String)>> {
+    let prop = document?
+        .root_element()
+        .children()
+        .find(|node| is(node, DAV, "prop"))?;
+    Some(
+        prop.children()
+            .filter(Node::is_element)
+            .map(|node| {
+                let name = node.tag_name();
+                (
+                    name.namespace().unwrap_or_default().to_string(),
+                    name.name().to_string(),
+                )
+            })
+            .collect(),
+    )
+}
+
+fn is(node: &Node, namespace: &str, name: &str) -> bool {
+    node.is_element()
+        && node.tag_name().namespace() == Some(namespace)
+        && node.tag_name().name() == name
+}
+
+// A 207 Multi-Status body under construction.
+struct Multistatus {
+    body: String,
+}
+
+impl Multistatus {
+    fn new() -> Self {
+        Multistatus {
+            body: format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                <d:multistatus xmlns:d=\"{DAV}\" xmlns:c=\"{CALDAV}\" xmlns:cs=\"{CALENDARSERVER}\">"
+            ),
+        }
+    }
+
+    // Properties the resource lacks are reported with a 404 status.
+    fn add(&mut self, resource: &Resource, props: &Option<Vec<(String, String)>>) {
+        let mut found = String::new();
+        let mut missing = String::new();
+        match props {
+            None => {
+                for (namespace, name) in resource.all_props() {
+                    found.extend(resource.prop(namespace, name));
+                }
+            }
+            Some(props) => {
+                for (namespace, name) in props {
+                    match resource.prop(namespace, name) {
+                        Some(value) => found.push_str(&value),
+                        None => missing
+                            .push_str(&format!("<x:{name} xmlns:x=\"{}\"/>", escape(namespace))),
+                    }
+                }
+            }
+        }
+        self.body
+            .push_str(&format!("<d:response><d:href>{}</d:href>", resource.href()));
+        for (props, status) in [(found, "200 OK"), (missing, "404 Not Found")] {
+            if !props.is_empty() {
+                self.body.push_str(&format!(
+                    "<d:propstat><d:prop>{props}</d:prop><d:status>HTTP/1.1 {status}</d:status></d:propstat>"
+                ));
+            }
+        }
+        self.body.push_str("</d:response>");
+    }
+
+    fn not_found(&mut self, href: &str) {
+        self.body.push_str(&format!(
+            "<d:response><d:href>{}</d:href><d:status>HTTP/1.1 404 Not Found</d:status></d:response>",
+            escape(href)
+        ));
+    }
+
+    fn finish(mut self) -> HttpResponse {
+        self.body.push_str("</d:multistatus>");
+        HttpResponse::build(StatusCode::MULTI_STATUS)
+            .content_type("application/xml; charset=utf-8")
+            .body(self.body)
+    }
+}
+
+async fn list_of(db: &UserTasksDB, user: &User, list_id: i64) -> Result<List, DbError> {
+    db.get_lists_by_user_id(user.user_id)
+        .await?
+        .into_iter()
+        .find(|list| list.list_id == list_id)
+        .ok_or(DbError::ListNotFound)
+}
+
+async fn tasks_of(db: &UserTasksDB, user: &User, list_id: i64) -> Result<Vec<Task>, DbError> {
+    let filter = TaskFilter {
+        list_id: Some(list_id),
+        ..Default::default()
+    };
+    db.get_tasks_by_user_id(user.user_id, TaskSort::default(), filter)
+        .await
+}
+
+fn find_task(tasks: &[Task], name: &str) -> Result<usize, DbError> {
+    tasks
+        .iter()
+        .position(|task| ical::resource_name(task) == name)
+        .ok_or(DbError::TaskNotFound)
+}
+
+// Depth 0 describes only the target; 1 and infinity also list its members.
+async fn propfind(
+    db: &UserTasksDB,
+    req: &HttpRequest,
+    user: &User,
+    target: Target,
+    body: &str,
+) -> Result<HttpResponse> {
+    let document = match body.trim() {
+        "" => None,
+        body => Some(Document::parse(body).map_err(invalid_xml)?),
+    };
+    let props = requested_props(document.as_ref());
+    let members = req
+        .headers()
+        .get("Depth")
+        .and_then(|depth| depth.to_str().ok())
+        != Some("0");
+    let mut multistatus = Multistatus::new();
+    match target {
+        Target::Home => {
+            multistatus.add(&Resource::Home(user), &props);
+            if members {
+                let revision = db.get_revision(user.user_id).await?;
+                for list in db.get_lists_by_user_id(user.user_id).await? {
+                    multistatus.add(&Resource::List(&list, revision), &props);
+                }
+            }
+        }
+        Target::List(list_id) => {
+            let list = list_of(db, user, list_id).await?;
+            let revision = db.get_revision(user.user_id).await?;
+            multistatus.add(&Resource::List(&list, revision), &props);
+            if members {
+                let tasks = tasks_of(db, user, list_id).await?;
+                for task in &tasks {
+                    multistatus.add(&Resource::Task(task, &tasks), &props);
+                }
+            }
+        }
+        Target::Task(list_id, name) => {
+            let tasks = tasks_of(db, user, list_id).await?;
+            let task = &tasks[find_task(&tasks, &name)?];
+            multistatus.add(&Resource::Task(task, &tasks), &props);
+        }
+    }
+    Ok(multistatus.finish())
+}
+
+// Whether a calendar-query filter can match a task. Only the component and a
+// COMPLETED <is-not-defined/> test, which asks for open tasks, are looked at;
+// anything else, such as time ranges, lets every task through.
+fn query_matches(filter: Option<Node>, task: &Task) -> bool {
+    let Some(filter) = filter else {
+        return true;
+    };
+    filter.descendants().all(|node| {
+        if is(&node, CALDAV, "comp-filter") {
+            matches!(node.attribute("name"), Some("VCALENDAR" | "VTODO"))
+        } else if is(&node, CALDAV, "prop-filter") && node.attribute("name") == Some("COMPLETED") {
+            let not_defined = node
+                .children()
+                .any(|child| is(&child, CALDAV, "is-not-defined"));
+            !(not_defined && task.done)
+        } else {
+            true
+        }
+    })
+}
+
+async fn report(db: &UserTasksDB, user: &User, list_id: i64, body: &str) -> Result<HttpResponse> {
+    let document = Document::parse(body).map_err(invalid_xml)?;
+    let root = document.root_element();
+    let props = requested_props(Some(&document));
+    let tasks = tasks_of(db, user, list_id).await?;
+    let mut multistatus = Multistatus::new();
+    if is(&root, CALDAV, "calendar-query") {
+        let filter = root.children().find(|node| is(node, CALDAV, "filter"));
+        for task in tasks.iter().filter(|task| query_matches(filter, task)) {
+            multistatus.add(&Resource::Task(task, &tasks), &props);
+        }
+    } else if is(&root, CALDAV, "calendar-multiget") {
+        for href in root.children().filter(|node| is(node, DAV, "href")) {
+            let href = href.text().unwrap_or_default().trim();
+            let task = match target(href) {
+                Some(Target::Task(href_list_id, name)) if href_list_id == list_id => {
+                    tasks.iter().find(|task| ical::resource_name(task) == name)
+                }
+                _ => None,
+            };
+            match task {
+                Some(task) => multistatus.add(&Resource::Task(task, &tasks), &props),
+                None => multistatus.not_found(href),
+            }
+        }
+    } else {
+        return Ok(dav_error(StatusCode::FORBIDDEN, "<d:supported-report/>"));
+    }
+    Ok(multistatus.finish())
+}
+
+async fn get_task(db: &UserTasksDB, user: &User, list_id: i64, name: &str) -> Result<HttpResponse> {
+    let tasks = tasks_of(db, user, list_id).await?;
+    let task = &tasks[find_task(&tasks, name)?];
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .insert_header((header::ETAG, ical::etag(task, &tasks)))
+        .body(ical::calendar(None, std::slice::from_ref(task), &tasks)))
+}
+
+// Creates or replaces a task from a calendar object. No ETag is sent back, as
+// the stored task rarely matches the object byte for byte, so clients fetch it
+// again.
+// A PUT naming another parent for an existing task.
+struct ParentChanged;
+
+async fn put_task(
+    db: &UserTasksDB,
+    req: &HttpRequest,
+    user: &User,
+    list_id: i64,
+    name: String,
+    body: &str,
+) -> Result<HttpResponse> {
+    list_of(db, user, list_id).await?;
+    let tasks = tasks_of(db, user, list_id).await?;
+    let existing = find_task(&tasks, &name).ok().map(|index| &tasks[index]);
+    if precondition_failed(
+        req,
+        existing.map(|task| ical::etag(task, &tasks)).as_deref(),
+    ) {
+        return Ok(precondition_response());
+    }
+    let vtodo = ical::parse_vtodo(body).map_err(|message| InvalidInput::field("body", message))?;
+
+    // a UID belongs to a single task of the user
+    let all_tasks = db
+        .get_tasks_by_user_id(user.user_id, TaskSort::default(), TaskFilter::default())
+        .await?;
+    if let Some(other) = all_tasks.iter().find(|task| {
+        ical::uid(task) == vtodo.uid && Some(task.task_id) != existing.map(|task| task.task_id)
+    }) {
+        return Ok(dav_error(
+            StatusCode::FORBIDDEN,
+            &format!(
+                "<c:no-uid-conflict><d:href>{}</d:href></c:no-uid-conflict>",
+                task_href(other)
+            ),
+        ));
+    }
+
+    let mut input = TaskInfo {
+        task_id: existing.map_or(0, |task| task.task_id),
+        list_id: Some(list_id),
+        parent_id: None,
+        task_title: vtodo.summary,
+        task_description: vtodo.description,
+        done: vtodo.completed,
+        due_at: vtodo.due_at,
+        start_at: vtodo.start_at,
+        priority: vtodo.priority,
+        tags: Some(vtodo.categories),
+        recurrence: vtodo.rrule,
+    }
+    .to_input()?;
+    input.due_kind = Some(vtodo.due_kind);
+    input.start_kind = Some(vtodo.start_kind);
+    // subtasks live under a parent from the same list
+    let parent_id = vtodo.related_to.and_then(|uid| {
+        tasks
+            .iter()
+            .find(|task| ical::uid(task) == uid)
+            .map(|task| task.task_id)
+    });
+    let response = match existing {
+        None => {
+            db.create_dav_task(
+                user.user_id,
+                list_id,
+                parent_id,
+                input,
+                vtodo.completed,
+                (vtodo.uid, name),
+            )
+            .await?;
+            HttpResponse::Created().finish()
+        }
+        Some(task) => {
+            // an existing task keeps its parent, there is no moving it under
+            // another one from here
+            let edited = db
+                .patch_task(
+                    task.task_id,
+                    user.user_id,
+                    move |current| {
+                        if current.parent_id != parent_id {
+                            return Err(ParentChanged);
+                        }
+                        Ok(TaskEdit {
+                            list_id: None,
+                            task: input,
+                            done: Some(vtodo.completed),
+                        })
+                    },
+                    // the client's time zone is unknown here
+                    UtcOffset::UTC,
+                )
+                .await?;
+            match edited {
+                Ok(_) => HttpResponse::NoContent().finish(),
+                Err(ParentChanged) => {
+                    dav_error(StatusCode::FORBIDDEN, "<c:valid-calendar-object-resource/>")
+                }
+            }
+        }
+    };
+    Ok(response)
+}
+
+async fn delete_task(
+    db: &UserTasksDB,
+    req: &HttpRequest,
+    user: &User,
+    list_id: i64,
+    name: &str,
+) -> Result<HttpResponse> {
+    let tasks = tasks_of(db, user, list_id).await?;
+    let task = &tasks[find_task(&tasks, name)?];
+    if precondition_failed(req, Some(&ical::etag(task, &tasks))) {
+        return Ok(precondition_response());
+    }
+    db.delete_task(task.task_id, user.user_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Every CalDAV request. Below /dav/, the user's principal and calendar home,
+// each list is a calendar collection at /dav/{list_id}/ holding its tasks as
+// VTODO resources.
+pub async fn dav(
+    user_tasks_db: Data<UserTasksDB>,
+    accepted: Data<AcceptedCredentials>,
+    req: HttpRequest,
+    body: Result<String, actix_web::Error>,
+) -> Result<HttpResponse> {
+    let body = error::text_body(body)?;
+    if req.method() == Method::OPTIONS {
+        return Ok(HttpResponse::Ok()
+            .insert_header(("DAV", "1, calendar-access"))
+            .insert_header((header::ALLOW, ALLOWED_METHODS))
+            .finish());
+    }
+    let Some(user) = authenticate(&user_tasks_db, &accepted, &req).await? else {
+        return Ok(challenge());
+    };
+    let Some(target) = target(req.path()) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let db = user_tasks_db.get_ref();
+    match (req.method().as_str(), target) {
+        ("PROPFIND", target) => propfind(db, &req, &user, target, &body).await,
+        ("REPORT", Target::List(list_id)) => report(db, &user, list_id, &body).await,
+        ("GET" | "HEAD", Target::Task(list_id, name)) => get_task(db, &user, list_id, &name).await,
+        ("PUT", Target::Task(list_id, name)) => {
+            put_task(db, &req, &user, list_id, name, &body).await
+        }
+        ("DELETE", Target::Task(list_id, name)) => {
+            delete_task(db, &req, &user, list_id, &name).await
+        }
+        _ => Ok(HttpResponse::MethodNotAllowed()
+            .insert_header((header::ALLOW, ALLOWED_METHODS))
+            .finish()),
+    }
+}
+
+// Lets clients find the server from its host name alone (RFC 6764).
+pub async fn well_known() -> HttpResponse {
+    HttpResponse::MovedPermanently()
+        .insert_header((header::LOCATION, HOME))
+        .finish()
+}
+
+#[derive(Serialize)]
+struct ResponseAppPassword {
+    app_password_id: i64,
+    name: String,
+    created_at: String,
+}
+
+impl From<AppPassword> for ResponseAppPassword {
+    fn from(app_password: AppPassword) -> Self {
+        ResponseAppPassword {
+            app_password_id: app_password.app_password_id,
+            name: app_password.name,
+            created_at: app_password.created_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AppPasswordResponse {
+    app_passwords: Vec<ResponseAppPassword>,
+    // the password just created; it is not shown again
+    password: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AppPasswordInfo {
+    name: String,
+}
+
+const APP_PASSWORD_NAME: Rule = Rule {
+    field: "name",
+    label: "the name",
+    trim: true,
+    min_chars: 1,
+    max_chars: 64,
+    charset: Charset::Line,
+};
+
+async fn app_password_response(
+    db: &UserTasksDB,
+    user_id: i64,
+    password: Option<String>,
+) -> Result<Json<AppPasswordResponse>> {
+    let app_passwords = db.get_app_passwords(user_id).await?;
+    Ok(Json(AppPasswordResponse {
+        app_passwords: app_passwords
+            .into_iter()
+            .map(ResponseAppPassword::from)
+            .collect(),
+        password,
+    }))
+}
+
+#[get("/app-passwords")]
+async fn app_passwords_list(
+    user_tasks_db: Data<UserTasksDB>,
+    user: CurrentUser,
+) -> Result<Json<AppPasswordResponse>> {
+    app_password_response(&user_tasks_db, user.user_id, None).await
+}
+
+#[post("/app-passwords")]
+async fn app_password_create(
+    user_tasks_db: Data<UserTasksDB>,
+    app_password_info: web::Json<AppPasswordInfo>,
+    user: CurrentUser,
+) -> Result<Json<AppPasswordResponse>> {
+    let mut name = app_password_info.into_inner().name;
+    if let Some(error) = APP_PASSWORD_NAME.check(&mut name) {
+        return Err(InvalidInput(vec![error]).into());
+    }
+    let password = uuid::Uuid::new_v4().simple().to_string();
+    user_tasks_db
+        .create_app_password(user.user_id, name, password.clone())
+        .await?;
+    app_password_response(&user_tasks_db, user.user_id, Some(password)).await
+}
+
+#[delete("/app-passwords/{app_password_id}")]
+async fn app_password_delete(
+    user_tasks_db: Data<UserTasksDB>,
+    accepted: Data<AcceptedCredentials>,
+    app_password_id: web::Path<i64>,
+    user: CurrentUser,
+) -> Result<Json<AppPasswordResponse>> {
+    user_tasks_db
+        .delete_app_password(*app_password_id, user.user_id)
+        .await?;
+    accepted.forget(*app_password_id);
+    app_password_response(&user_tasks_db, user.user_id, None).await
+}
@@ -1,11 +1,25 @@
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display, path::Path, sync::Arc};
 
-use sqlite::State;
+use actix_web::web;
+use serde::Deserialize;
+use sqlite::{ffi, Connection, State, Statement, Value};
+use time::UtcOffset;
+
+use crate::migrations;
+use crate::password::{self, Verification};
+use crate::pool::Pool;
+use crate::recurrence;
+
+// Distance between neighbouring task positions, leaving room to move a task
+// between two others without renumbering its siblings.
+const POSITION_GAP: i64 = 1024;
 
 pub struct User {
     pub user_id: i64,
     pub username: String,
     password: String,
+    // Secret part of the URL of the user's calendar feed; no feed while `None`.
+    pub feed_token: Option<String>,
 }
 
 impl Display for User {
@@ -16,139 +30,1880 @@ impl Display for User {
 
 pub struct Task {
     pub task_id: i64,
+    pub list_id: i64,
+    // Set on subtasks, which are only nested one level deep.
+    pub parent_id: Option<i64>,
+    // Manual order among the tasks with the same parent, or among the user's
+    // top level tasks.
+    pub position: i64,
     pub title: String,
     pub description: String,
+    pub done: bool,
+    // UTC, RFC 3339; set while the task is done.
+    pub completed_at: Option<String>,
+    // UTC, RFC 3339.
+    pub due_at: Option<String>,
+    pub start_at: Option<String>,
+    // 0 (none) to 3 (high).
+    pub priority: i64,
+    // Names of the task's tags, filled in after the row is read.
+    pub tags: Vec<String>,
+    // RRULE subset in canonical form, see `recurrence::Recurrence`.
+    pub recurrence: Option<String>,
+    // The iCalendar UID and CalDAV resource name a client gave the task; tasks
+    // created anywhere else get ones derived from `task_id`, see `ical`.
+    pub dav_uid: Option<String>,
+    pub dav_name: Option<String>,
+    // How a CalDAV client wrote the dates, `None` for a UTC time.
+    pub due_kind: Option<DateKind>,
+    pub start_kind: Option<DateKind>,
 }
 
-pub struct UserTasksDB {
-    connection: sqlite::Connection,
+// Dates a CalDAV client gave without a time zone, which `due_at` and
+// `start_at` hold as if they were UTC and which are sent back in the same form.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DateKind {
+    // all day, stored as midnight
+    Date,
+    // a wall clock time, whatever the time zone
+    Floating,
 }
 
-impl Clone for UserTasksDB {
-    fn clone(&self) -> Self {
-        Self {
-            connection: sqlite::open("db.sql").unwrap(),
+impl DateKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            DateKind::Date => "date",
+            DateKind::Floating => "floating",
         }
     }
-}
 
-impl UserTasksDB {
-    pub fn new() -> UserTasksDB {
-        UserTasksDB {
-            connection: sqlite::open("db.sql").unwrap(),
+    fn parse(value: Option<String>) -> Option<DateKind> {
+        match value.as_deref() {
+            Some("date") => Some(DateKind::Date),
+            Some("floating") => Some(DateKind::Floating),
+            _ => None,
         }
     }
+}
 
-    pub fn reset(&mut self) {
-        let query: &str = "
-        DROP TABLE   IF EXISTS users;
-        CREATE TABLE users (
-            user_id INTEGER NOT NULL UNIQUE, 
-            username TEXT NOT NULL UNIQUE, 
-            password TEXT NOT NULL,
-            PRIMARY KEY('user_id' AUTOINCREMENT)
-        );
-        INSERT INTO users VALUES(0, 'user0', 'password0');
-        INSERT INTO users VALUES(NULL, 'user1', 'password1');
-        INSERT INTO users VALUES(NULL, 'user2', 'password2'); 
-    
-    
-        DROP TABLE IF EXISTS tasks;
-        CREATE TABLE tasks (
-            task_id INTEGER NOT NULL UNIQUE,
-            user_id INTEGER NOT NULL,
-            title TEXT NOT NULL UNIQUE,
-            description TEXT,
-            PRIMARY KEY('task_id' AUTOINCREMENT),
-            FOREIGN KEY('user_id') REFERENCES users('user_id')
-        );
-        INSERT INTO tasks VALUES (NULL, 1, 'title 11', 'description 11');
-        INSERT INTO tasks VALUES (NULL, 2, 'title 21', 'description 21');
-        INSERT INTO tasks VALUES (NULL, 2, 'title 31', 'description 31');
-    
-        ";
+fn kind_column(kind: Option<DateKind>) -> Value {
+    kind.map(|kind| kind.as_str().to_string()).into()
+}
+
+// An edit of a task: the list to move it to, its new fields and whether it is
+// done, the list and `done` being left alone when `None`.
+pub struct TaskEdit {
+    pub list_id: Option<i64>,
+    pub task: TaskInput,
+    pub done: Option<bool>,
+}
+
+// The user editable fields of a task.
+pub struct TaskInput {
+    pub title: String,
+    pub description: String,
+    pub due_at: Option<String>,
+    pub start_at: Option<String>,
+    pub priority: i64,
+    // `None` leaves the tags of an existing task untouched.
+    pub tags: Option<Vec<String>>,
+    pub recurrence: Option<String>,
+    // Set by CalDAV; `None` keeps the kind of a date that stays the same and
+    // turns a changed one into a UTC time.
+    pub due_kind: Option<Option<DateKind>>,
+    pub start_kind: Option<Option<DateKind>>,
+}
 
-        self.connection.execute(query).unwrap();
+// Which of a user's tasks to list.
+#[derive(Default)]
+pub struct TaskFilter {
+    pub list_id: Option<i64>,
+    // Only tasks carrying every one of these tags.
+    pub tags: Vec<String>,
+}
+
+impl TaskFilter {
+    pub fn matches(&self, task: &Task) -> bool {
+        self.list_id.is_none_or(|list_id| list_id == task.list_id)
+            && self.tags.iter().all(|name| {
+                task.tags
+                    .iter()
+                    .any(|tag| tag.to_lowercase() == name.to_lowercase())
+            })
     }
+}
 
-    pub fn get_user_by_credentials(&self, username: &str, password: &str) -> Vec<User> {
-        let query = "SELECT * from users WHERE username = ? AND password = ? ;";
-        let mut statement = self.connection.prepare(query).unwrap();
-        statement.bind(&[(1, username), (2, password)][..]).unwrap();
+pub struct Tag {
+    pub tag_id: i64,
+    pub name: String,
+}
 
-        let mut users: Vec<User> = vec![];
-        while let Ok(State::Row) = statement.next() {
-            users.push(User {
-                user_id: statement.read::<i64, _>("user_id").unwrap(),
-                username: statement.read::<String, _>("username").unwrap(),
-                password: statement.read::<String, _>("password").unwrap(),
-            });
+// A task matching a search, with the matched terms of its title and description
+// wrapped in `<mark>` and everything else HTML escaped.
+pub struct SearchHit {
+    pub task_id: i64,
+    pub list_id: i64,
+    pub parent_id: Option<i64>,
+    pub done: bool,
+    pub title: String,
+    pub snippet: String,
+    // bm25 score, lower is better.
+    pub rank: f64,
+}
+
+// A password for a single CalDAV client, so that the account password need not
+// be stored in it and each can be revoked on its own.
+pub struct AppPassword {
+    pub app_password_id: i64,
+    pub name: String,
+    pub created_at: String,
+    password: String,
+}
+
+pub struct List {
+    pub list_id: i64,
+    pub name: String,
+    pub open_tasks: i64,
+}
+
+// A task read from an import document. `key` and `parent_key` are the ids the
+// document uses; subtasks go into their parent's list.
+pub struct ImportTask {
+    pub row: usize,
+    pub key: i64,
+    pub parent_key: Option<i64>,
+    // the user's first list when `None`; created when the user has no such list
+    pub list: Option<String>,
+    pub task: TaskInput,
+    pub done: bool,
+    pub completed_at: Option<String>,
+}
+
+// A row of an import document that was not imported, and why.
+pub struct ImportIssue {
+    pub row: usize,
+    pub title: String,
+    pub message: String,
+}
+
+#[derive(Default)]
+pub struct ImportReport {
+    pub created: usize,
+    pub lists_created: Vec<String>,
+    // rows whose title is already taken among their siblings, which are skipped
+    pub duplicates: Vec<ImportIssue>,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    Manual,
+    Priority,
+    DueDate,
+    Created,
+    Title,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+// How to order a task list.
+#[derive(Clone, Copy, Default)]
+pub struct TaskSort {
+    pub sort: SortKey,
+    pub order: Option<SortOrder>,
+}
+
+impl TaskSort {
+    // Only ever built from the enums above, so it is safe to splice into SQL.
+    fn order_by(&self) -> String {
+        let order = self.order.unwrap_or(match self.sort {
+            SortKey::Priority => SortOrder::Desc,
+            _ => SortOrder::Asc,
+        });
+        let direction = match order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+        match self.sort {
+            SortKey::Manual => format!("position {direction}, task_id ASC"),
+            SortKey::Created => format!("task_id {direction}"),
+            SortKey::Priority => format!("priority {direction}, task_id ASC"),
+            SortKey::DueDate => format!("due_at IS NULL, due_at {direction}, task_id ASC"),
+            SortKey::Title => format!("title COLLATE NOCASE {direction}, task_id ASC"),
         }
-        return users;
     }
+}
 
-    pub fn get_tasks_by_user_id(&self, user_id: i64) -> Vec<Task> {
-        let query = "SELECT * from tasks WHERE user_id = ? ;";
-        let mut statement = self.connection.prepare(query).unwrap();
-        statement.bind((1, user_id)).unwrap();
+#[derive(Debug)]
+pub enum DbError {
+    TaskNotFound,
+    // Holds the clashing title once known; sibling titles are unique per list.
+    TitleConflict(String),
+    UsernameConflict,
+    TagNotFound,
+    TagConflict,
+    ListNotFound,
+    ListConflict,
+    ParentNotFound,
+    NestedSubtask,
+    // Skipping an occurrence of a task without a recurrence rule.
+    NotRecurring,
+    // No user has the calendar feed token that was asked for.
+    FeedNotFound,
+    AppPasswordNotFound,
+    // The database could not be reached: busy, locked or failing to open.
+    Unavailable,
+    Internal,
+}
 
-        let mut tasks: Vec<Task> = vec![];
-        while let Ok(State::Row) = statement.next() {
-            tasks.push(Task {
-                task_id: statement.read::<i64, _>("task_id").unwrap(),
-                title: statement.read::<String, _>("title").unwrap(),
-                description: statement.read::<String, _>("description").unwrap(),
-            });
+impl Display for DbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DbError::TaskNotFound => write!(f, "task not found"),
+            DbError::TitleConflict(title) => {
+                write!(f, "a task named '{title}' already exists in this list")
+            }
+            DbError::UsernameConflict => write!(f, "username already taken"),
+            DbError::TagNotFound => write!(f, "tag not found"),
+            DbError::TagConflict => write!(f, "a tag with this name already exists"),
+            DbError::ListNotFound => write!(f, "list not found"),
+            DbError::ListConflict => write!(f, "a list with this name already exists"),
+            DbError::ParentNotFound => write!(f, "parent task not found"),
+            DbError::NestedSubtask => write!(f, "subtasks cannot have subtasks"),
+            DbError::NotRecurring => write!(f, "the task does not repeat"),
+            DbError::FeedNotFound => write!(f, "feed not found"),
+            DbError::AppPasswordNotFound => write!(f, "app password not found"),
+            DbError::Unavailable => write!(f, "database unavailable"),
+            DbError::Internal => write!(f, "database error"),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl DbError {
+    // Stable identifier sent to clients along with the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DbError::TaskNotFound => "task_not_found",
+            DbError::TitleConflict(_) => "title_conflict",
+            DbError::UsernameConflict => "username_conflict",
+            DbError::TagNotFound => "tag_not_found",
+            DbError::TagConflict => "tag_conflict",
+            DbError::ListNotFound => "list_not_found",
+            DbError::ListConflict => "list_conflict",
+            DbError::ParentNotFound => "parent_not_found",
+            DbError::NestedSubtask => "nested_subtask",
+            DbError::NotRecurring => "not_recurring",
+            DbError::FeedNotFound => "feed_not_found",
+            DbError::AppPasswordNotFound => "app_password_not_found",
+            DbError::Unavailable => "unavailable",
+            DbError::Internal => "internal",
+        }
+    }
+}
+
+impl DbError {
+    // Fills in the title of a conflict raised by the database.
+    fn titled(self, title: &str) -> Self {
+        match self {
+            DbError::TitleConflict(_) => DbError::TitleConflict(title.to_string()),
+            err => err,
         }
-        return tasks;
     }
+}
 
-    pub fn create_task(&self, user_id: i64, title: String, description: String) -> bool {
-        let query = format!(
-            "INSERT INTO tasks VALUES (NULL, {}, '{}', '{}');",
-            user_id, title, description
-        );
-        let result = &self.connection.execute(query);
-        match result {
-            Ok(_) => true,
-            Err(err) => {
+impl From<sqlite::Error> for DbError {
+    fn from(err: sqlite::Error) -> Self {
+        let message = err.message.clone().unwrap_or_default();
+        match err.code.map(|code| code as i32) {
+            Some(ffi::SQLITE_CONSTRAINT) if message.contains("tasks_sibling_title") => {
+                DbError::TitleConflict(String::new())
+            }
+            Some(ffi::SQLITE_CONSTRAINT) if message.contains("users.username") => {
+                DbError::UsernameConflict
+            }
+            Some(ffi::SQLITE_CONSTRAINT) if message.contains("tags.name") => DbError::TagConflict,
+            Some(ffi::SQLITE_CONSTRAINT) if message.contains("lists.name") => DbError::ListConflict,
+            Some(ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED | ffi::SQLITE_CANTOPEN) => {
                 println!("{err}");
-                false
+                DbError::Unavailable
             }
+            _ => {
+                println!("{err}");
+                DbError::Internal
+            }
+        }
+    }
+}
+
+// Typed mapping from the current row of a statement.
+trait FromRow: Sized {
+    fn from_row(statement: &Statement) -> sqlite::Result<Self>;
+}
+
+impl FromRow for User {
+    fn from_row(statement: &Statement) -> sqlite::Result<Self> {
+        Ok(User {
+            user_id: statement.read::<i64, _>("user_id")?,
+            username: statement.read::<String, _>("username")?,
+            password: statement.read::<String, _>("password")?,
+            feed_token: statement.read::<Option<String>, _>("feed_token")?,
+        })
+    }
+}
+
+impl FromRow for Task {
+    fn from_row(statement: &Statement) -> sqlite::Result<Self> {
+        Ok(Task {
+            task_id: statement.read::<i64, _>("task_id")?,
+            list_id: statement.read::<i64, _>("list_id")?,
+            parent_id: statement.read::<Option<i64>, _>("parent_id")?,
+            position: statement.read::<i64, _>("position")?,
+            title: statement.read::<String, _>("title")?,
+            description: statement
+                .read::<Option<String>, _>("description")?
+                .unwrap_or_default(),
+            done: statement.read::<i64, _>("done")? != 0,
+            completed_at: statement.read::<Option<String>, _>("completed_at")?,
+            due_at: statement.read::<Option<String>, _>("due_at")?,
+            start_at: statement.read::<Option<String>, _>("start_at")?,
+            priority: statement.read::<i64, _>("priority")?,
+            tags: vec![],
+            recurrence: statement.read::<Option<String>, _>("recurrence")?,
+            dav_uid: statement.read::<Option<String>, _>("dav_uid")?,
+            dav_name: statement.read::<Option<String>, _>("dav_name")?,
+            due_kind: DateKind::parse(statement.read::<Option<String>, _>("due_kind")?),
+            start_kind: DateKind::parse(statement.read::<Option<String>, _>("start_kind")?),
+        })
+    }
+}
+
+impl FromRow for Tag {
+    fn from_row(statement: &Statement) -> sqlite::Result<Self> {
+        Ok(Tag {
+            tag_id: statement.read::<i64, _>("tag_id")?,
+            name: statement.read::<String, _>("name")?,
+        })
+    }
+}
+
+impl FromRow for AppPassword {
+    fn from_row(statement: &Statement) -> sqlite::Result<Self> {
+        Ok(AppPassword {
+            app_password_id: statement.read::<i64, _>("app_password_id")?,
+            name: statement.read::<String, _>("name")?,
+            created_at: statement.read::<String, _>("created_at")?,
+            password: statement.read::<String, _>("password")?,
+        })
+    }
+}
+
+impl FromRow for List {
+    fn from_row(statement: &Statement) -> sqlite::Result<Self> {
+        Ok(List {
+            list_id: statement.read::<i64, _>("list_id")?,
+            name: statement.read::<String, _>("name")?,
+            open_tasks: statement.read::<i64, _>("open_tasks")?,
+        })
+    }
+}
+
+// Marks around matched terms; they cannot appear in escaped text.
+const MATCH_START: char = '\u{1}';
+const MATCH_END: char = '\u{2}';
+
+impl FromRow for SearchHit {
+    fn from_row(statement: &Statement) -> sqlite::Result<Self> {
+        Ok(SearchHit {
+            task_id: statement.read::<i64, _>("task_id")?,
+            list_id: statement.read::<i64, _>("list_id")?,
+            parent_id: statement.read::<Option<i64>, _>("parent_id")?,
+            done: statement.read::<i64, _>("done")? != 0,
+            title: marked_html(&statement.read::<String, _>("title_match")?),
+            snippet: marked_html(&statement.read::<String, _>("snippet")?),
+            rank: statement.read::<f64, _>("rank")?,
+        })
+    }
+}
+
+fn marked_html(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
         }
     }
+    html
+}
+
+// One row of the task_tags join, with the tag resolved to its name.
+struct TaskTag {
+    task_id: i64,
+    name: String,
+}
+
+impl FromRow for TaskTag {
+    fn from_row(statement: &Statement) -> sqlite::Result<Self> {
+        Ok(TaskTag {
+            task_id: statement.read::<i64, _>("task_id")?,
+            name: statement.read::<String, _>("name")?,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct UserTasksDB {
+    pool: Arc<Pool>,
+}
+
+impl UserTasksDB {
+    pub fn new(path: &Path, pool_size: usize) -> Result<UserTasksDB, DbError> {
+        Ok(UserTasksDB {
+            pool: Arc::new(Pool::new(path, pool_size)?),
+        })
+    }
+
+    pub fn migrate(&self) -> Result<(), DbError> {
+        let connection = self.pool.get()?;
+        Ok(migrations::run(&connection)?)
+    }
+
+    // Demo users and tasks, only loaded when explicitly asked for.
+    pub fn seed(&self) -> Result<(), DbError> {
+        let query: &str = "
+        INSERT OR IGNORE INTO users (user_id, username, password) VALUES (0, 'user0', 'password0');
+        INSERT OR IGNORE INTO users (user_id, username, password) VALUES (1, 'user1', 'password1');
+        INSERT OR IGNORE INTO users (user_id, username, password) VALUES (2, 'user2', 'password2');
+
+        INSERT OR IGNORE INTO lists (user_id, name) VALUES (0, 'Inbox');
+        INSERT OR IGNORE INTO lists (user_id, name) VALUES (1, 'Inbox');
+        INSERT OR IGNORE INTO lists (user_id, name) VALUES (2, 'Inbox');
+
+        INSERT OR IGNORE INTO tasks (user_id, list_id, title, description)
+            SELECT 1, list_id, 'title 11', 'description 11' FROM lists WHERE user_id = 1;
+        INSERT OR IGNORE INTO tasks (user_id, list_id, title, description)
+            SELECT 2, list_id, 'title 21', 'description 21' FROM lists WHERE user_id = 2;
+        INSERT OR IGNORE INTO tasks (user_id, list_id, title, description)
+            SELECT 2, list_id, 'title 31', 'description 31' FROM lists WHERE user_id = 2;
+
+        DELETE FROM tasks_search;
+        INSERT INTO tasks_search (rowid, title, description)
+            SELECT task_id, title, IFNULL(description, '') FROM tasks;
+        ";
+
+        Ok(self.pool.get()?.execute(query)?)
+    }
+
+    pub async fn get_user_by_credentials(
+        &self,
+        username: String,
+        password: String,
+    ) -> Result<Vec<User>, DbError> {
+        self.run(move |connection| {
+            let users = select_user_by_username(connection, &username)?;
+
+            let mut matching_users: Vec<User> = vec![];
+            for mut user in users {
+                match password::verify(&password, &user.password) {
+                    Verification::Valid => matching_users.push(user),
+                    Verification::ValidLegacy => {
+                        // upgrade the plaintext row now that we know the password
+                        let password_hash = password::hash(&password);
+                        execute(
+                            connection,
+                            "UPDATE users SET password = ? WHERE user_id = ?;",
+                            &[password_hash.as_str().into(), user.user_id.into()],
+                        )?;
+                        user.password = password_hash;
+                        matching_users.push(user);
+                    }
+                    Verification::Invalid => {}
+                }
+            }
+            Ok(matching_users)
+        })
+        .await
+    }
+
+    pub async fn get_user_by_username(&self, username: String) -> Result<Vec<User>, DbError> {
+        self.run(move |connection| select_user_by_username(connection, &username))
+            .await
+    }
+
+    pub async fn create_user(&self, username: String, password: String) -> Result<(), DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                let user_id = scalar(
+                    connection,
+                    "INSERT INTO users (username, password) VALUES (?, ?) RETURNING user_id;",
+                    &[username.into(), password::hash(&password).into()],
+                )?;
+                // every user starts with an empty "Inbox"
+                default_list(connection, user_id.unwrap_or_default())?;
+                Ok(())
+            })
+        })
+        .await
+    }
+
+    pub async fn get_user(&self, user_id: i64) -> Result<Option<User>, DbError> {
+        self.run(move |connection| {
+            query(
+                connection,
+                "SELECT * from users WHERE user_id = ?;",
+                &[user_id.into()],
+            )
+            .map(|users| users.into_iter().next())
+        })
+        .await
+    }
+
+    pub async fn get_user_by_feed_token(&self, token: String) -> Result<Option<User>, DbError> {
+        self.run(move |connection| {
+            query(
+                connection,
+                "SELECT * from users WHERE feed_token = ?;",
+                &[token.into()],
+            )
+            .map(|users| users.into_iter().next())
+        })
+        .await
+    }
+
+    // Replaces the user's feed token, `None` turning the feed off.
+    pub async fn set_feed_token(&self, user_id: i64, token: Option<String>) -> Result<(), DbError> {
+        self.run(move |connection| {
+            execute(
+                connection,
+                "UPDATE users SET feed_token = ? WHERE user_id = ?;",
+                &[token.into(), user_id.into()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    // The user owning `username` and the app password `password` is, if it is
+    // one. Only the app password stored under the password's prefix is hashed.
+    pub async fn get_user_by_app_password(
+        &self,
+        username: String,
+        password: String,
+    ) -> Result<Option<(User, i64)>, DbError> {
+        self.run(move |connection| {
+            let prefix = app_password_prefix(&password);
+            for user in select_user_by_username(connection, &username)? {
+                let app_passwords: Vec<AppPassword> = query(
+                    connection,
+                    "SELECT * from app_passwords WHERE user_id = ? AND prefix = ?;",
+                    &[user.user_id.into(), prefix.as_str().into()],
+                )?;
+                let Some(app_password) = app_passwords.into_iter().find(|app_password| {
+                    matches!(
+                        password::verify(&password, &app_password.password),
+                        Verification::Valid
+                    )
+                }) else {
+                    continue;
+                };
+                return Ok(Some((user, app_password.app_password_id)));
+            }
+            Ok(None)
+        })
+        .await
+    }
+
+    pub async fn get_app_passwords(&self, user_id: i64) -> Result<Vec<AppPassword>, DbError> {
+        self.run(move |connection| {
+            query(
+                connection,
+                "SELECT * from app_passwords WHERE user_id = ? ORDER BY app_password_id;",
+                &[user_id.into()],
+            )
+        })
+        .await
+    }
+
+    // Stores a hash of `password` under `name`, along with its prefix to find it by.
+    pub async fn create_app_password(
+        &self,
+        user_id: i64,
+        name: String,
+        password: String,
+    ) -> Result<(), DbError> {
+        self.run(move |connection| {
+            execute(
+                connection,
+                "INSERT INTO app_passwords (user_id, name, password, prefix) VALUES (?, ?, ?, ?);",
+                &[
+                    user_id.into(),
+                    name.into(),
+                    password::hash(&password).into(),
+                    app_password_prefix(&password).into(),
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn delete_app_password(
+        &self,
+        app_password_id: i64,
+        user_id: i64,
+    ) -> Result<(), DbError> {
+        self.run(move |connection| {
+            let deleted = execute(
+                connection,
+                "DELETE FROM app_passwords WHERE app_password_id = ? AND user_id = ?;",
+                &[app_password_id.into(), user_id.into()],
+            )?;
+            if deleted == 0 {
+                return Err(DbError::AppPasswordNotFound);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn get_tasks_by_user_id(
+        &self,
+        user_id: i64,
+        sort: TaskSort,
+        filter: TaskFilter,
+    ) -> Result<Vec<Task>, DbError> {
+        self.run(move |connection| {
+            let mut conditions = String::new();
+            let mut params: Vec<Value> = vec![user_id.into()];
+            if let Some(list_id) = filter.list_id {
+                conditions.push_str(" AND list_id = ?");
+                params.push(list_id.into());
+            }
+            let tags = filter.tags;
+            if !tags.is_empty() {
+                conditions += &format!(
+                    " AND task_id IN (
+                        SELECT task_tags.task_id FROM task_tags
+                        JOIN tags ON tags.tag_id = task_tags.tag_id
+                        WHERE tags.user_id = ? AND tags.name IN ({})
+                        GROUP BY task_tags.task_id
+                        HAVING COUNT(DISTINCT tags.tag_id) = ?
+                    )",
+                    vec!["?"; tags.len()].join(", ")
+                );
+                params.push(user_id.into());
+                let count = tags.len() as i64;
+                params.extend(tags.into_iter().map(Value::from));
+                params.push(count.into());
+            }
+            let mut tasks: Vec<Task> = query(
+                connection,
+                &format!(
+                    "SELECT * from tasks WHERE user_id = ?{conditions} ORDER BY {} ;",
+                    sort.order_by()
+                ),
+                &params,
+            )?;
+            attach_tags(connection, user_id, &mut tasks)?;
+            Ok(tasks)
+        })
+        .await
+    }
+
+    // Unknown ids are left out.
+    pub async fn get_tasks_by_ids(
+        &self,
+        user_id: i64,
+        task_ids: Vec<i64>,
+    ) -> Result<Vec<Task>, DbError> {
+        self.run(move |connection| {
+            if task_ids.is_empty() {
+                return Ok(vec![]);
+            }
+            let mut params: Vec<Value> = vec![user_id.into()];
+            params.extend(task_ids.iter().map(|task_id| Value::from(*task_id)));
+            let mut tasks: Vec<Task> = query(
+                connection,
+                &format!(
+                    "SELECT * from tasks WHERE user_id = ? AND task_id IN ({}) ORDER BY task_id;",
+                    vec!["?"; task_ids.len()].join(", ")
+                ),
+                &params,
+            )?;
+            attach_tags(connection, user_id, &mut tasks)?;
+            Ok(tasks)
+        })
+        .await
+    }
+
+    // The task together with its parent and subtasks, the tasks a change to it
+    // can reach. Empty when the task does not exist.
+    pub async fn task_family(&self, task_id: i64, user_id: i64) -> Result<Vec<i64>, DbError> {
+        self.run(move |connection| {
+            let tasks: Vec<Task> = query(
+                connection,
+                "SELECT * from tasks WHERE user_id = ?1 AND (
+                    task_id = ?2
+                    OR parent_id = ?2
+                    OR task_id = (SELECT parent_id FROM tasks WHERE task_id = ?2)
+                ) ORDER BY task_id;",
+                &[user_id.into(), task_id.into()],
+            )?;
+            Ok(tasks.into_iter().map(|task| task.task_id).collect())
+        })
+        .await
+    }
+
+    // The revision counts the changes made to a user's tasks, so a client can
+    // tell whether its copy missed one.
+    pub async fn get_revision(&self, user_id: i64) -> Result<i64, DbError> {
+        self.run(move |connection| {
+            scalar(
+                connection,
+                "SELECT revision FROM users WHERE user_id = ?;",
+                &[user_id.into()],
+            )
+            .map(Option::unwrap_or_default)
+        })
+        .await
+    }
+
+    pub async fn get_task(&self, task_id: i64, user_id: i64) -> Result<Task, DbError> {
+        self.run(move |connection| {
+            let mut task = select_task(connection, task_id, user_id)?;
+            attach_tags(connection, user_id, std::slice::from_mut(&mut task))?;
+            Ok(task)
+        })
+        .await
+    }
 
-    pub fn update_task(
+    // Creates the task in `list_id`, or in the user's first list when `None`.
+    // A subtask always lives in its parent's list and is added after its siblings.
+    // Returns the task's id and, as every change to tasks does, the revision it
+    // was recorded under.
+    pub async fn create_task(
+        &self,
+        user_id: i64,
+        list_id: Option<i64>,
+        parent_id: Option<i64>,
+        task: TaskInput,
+        done: bool,
+    ) -> Result<(i64, i64), DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                let task_id =
+                    insert_task(connection, user_id, list_id, parent_id, task, done, None)?;
+                Ok((task_id, bump_revision(connection, user_id)?))
+            })
+        })
+        .await
+    }
+
+    // Creates a task under the UID and resource name a CalDAV client chose, in
+    // that order in `resource`.
+    pub async fn create_dav_task(
+        &self,
+        user_id: i64,
+        list_id: i64,
+        parent_id: Option<i64>,
+        task: TaskInput,
+        done: bool,
+        resource: (String, String),
+    ) -> Result<i64, DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                let task_id = insert_task(
+                    connection,
+                    user_id,
+                    Some(list_id),
+                    parent_id,
+                    task,
+                    done,
+                    None,
+                )?;
+                execute(
+                    connection,
+                    "UPDATE tasks SET dav_uid = ?, dav_name = ? WHERE task_id = ?;",
+                    &[resource.0.into(), resource.1.into(), task_id.into()],
+                )?;
+                bump_revision(connection, user_id)
+            })
+        })
+        .await
+    }
+
+    // Marks a task done or not done. Completing an already completed task keeps
+    // its original completion time. Completing a parent completes its subtasks,
+    // and the parent of a subtask follows whether all of its subtasks are done.
+    // Completing a recurring task moves it to its next occurrence instead, as
+    // long as the series goes on; `offset` is the client's UTC offset.
+    pub async fn set_task_done(
         &self,
         task_id: i64,
         user_id: i64,
-        title: String,
-        description: String,
-    ) -> bool {
-        let query = format!(
-            "UPDATE tasks 
-            SET title = '{}', description = '{}'
-            WHERE task_id = {} AND user_id = {};",
-            title, description, task_id, user_id
-        );
-        //println!("{query}");
-        let result = &self.connection.execute(query);
-        match result {
-            Ok(_) => true,
-            Err(_) => false,
+        done: bool,
+        offset: UtcOffset,
+    ) -> Result<i64, DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                mark_done(connection, task_id, user_id, done, offset)?;
+                bump_revision(connection, user_id)
+            })
+        })
+        .await
+    }
+
+    // Moves a recurring task on to its next occurrence without completing it.
+    // Skipping the last occurrence ends the series and completes the task.
+    pub async fn skip_occurrence(
+        &self,
+        task_id: i64,
+        user_id: i64,
+        offset: UtcOffset,
+    ) -> Result<i64, DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                let task = select_task(connection, task_id, user_id)?;
+                if task.recurrence.is_none() {
+                    return Err(DbError::NotRecurring);
+                }
+                match recurrence::reschedule(&task, offset) {
+                    Some(next) => reschedule_task(connection, &task, next)?,
+                    None => {
+                        execute(
+                            connection,
+                            "UPDATE tasks
+                            SET done = 1,
+                                completed_at = IFNULL(completed_at, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+                            WHERE task_id = ?1 OR parent_id = ?1;",
+                            &[task_id.into()],
+                        )?;
+                        roll_up(connection, task.parent_id)?;
+                    }
+                }
+                bump_revision(connection, user_id)
+            })
+        })
+        .await
+    }
+
+    // Orders the subtasks of a parent: `children` first in the given order, then
+    // any subtask left out, keeping their previous order.
+    pub async fn reorder_children(
+        &self,
+        parent_id: i64,
+        user_id: i64,
+        children: Vec<i64>,
+    ) -> Result<i64, DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                select_task(connection, parent_id, user_id)?;
+                let current: Vec<Task> = query(
+                    connection,
+                    "SELECT * from tasks WHERE parent_id = ? ORDER BY position, task_id;",
+                    &[parent_id.into()],
+                )?;
+                if children
+                    .iter()
+                    .any(|child| !current.iter().any(|task| task.task_id == *child))
+                {
+                    return Err(DbError::TaskNotFound);
+                }
+                let order = children.iter().copied().chain(
+                    current
+                        .iter()
+                        .map(|task| task.task_id)
+                        .filter(|task_id| !children.contains(task_id)),
+                );
+                renumber(connection, order)?;
+                bump_revision(connection, user_id)
+            })
+        })
+        .await
+    }
+
+    // Moves a task among its siblings, right before `before_id` or right after
+    // `after_id`, or to the end when neither is given. Only the moved task is
+    // written unless there is no room left between its new neighbours. Returns
+    // the tasks whose position changed and the revision.
+    pub async fn place_task(
+        &self,
+        task_id: i64,
+        user_id: i64,
+        before_id: Option<i64>,
+        after_id: Option<i64>,
+    ) -> Result<(Vec<i64>, i64), DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                let task = select_task(connection, task_id, user_id)?;
+                // an anchor from another list or parent is not a sibling
+                let siblings: Vec<Task> = query(
+                    connection,
+                    "SELECT * from tasks
+                    WHERE user_id = ? AND list_id IS ? AND parent_id IS ? AND task_id != ?
+                    ORDER BY position, task_id;",
+                    &[
+                        user_id.into(),
+                        task.list_id.into(),
+                        task.parent_id.into(),
+                        task_id.into(),
+                    ],
+                )?;
+                let index_of = |id: i64| {
+                    siblings
+                        .iter()
+                        .position(|sibling| sibling.task_id == id)
+                        .ok_or(DbError::TaskNotFound)
+                };
+                let index = match (before_id, after_id) {
+                    (Some(before_id), _) => index_of(before_id)?,
+                    (None, Some(after_id)) => index_of(after_id)? + 1,
+                    (None, None) => siblings.len(),
+                };
+                let lower = index.checked_sub(1).map(|index| siblings[index].position);
+                let upper = siblings.get(index).map(|sibling| sibling.position);
+                let position = match (lower, upper) {
+                    (None, None) => POSITION_GAP,
+                    (Some(lower), None) => lower + POSITION_GAP,
+                    (None, Some(upper)) => upper - POSITION_GAP,
+                    (Some(lower), Some(upper)) if upper - lower >= 2 => lower + (upper - lower) / 2,
+                    _ => {
+                        let mut order: Vec<i64> =
+                            siblings.iter().map(|sibling| sibling.task_id).collect();
+                        order.insert(index, task_id);
+                        renumber(connection, order.iter().copied())?;
+                        return Ok((order, bump_revision(connection, user_id)?));
+                    }
+                };
+                execute(
+                    connection,
+                    "UPDATE tasks SET position = ? WHERE task_id = ?;",
+                    &[position.into(), task_id.into()],
+                )?;
+                Ok((vec![task_id], bump_revision(connection, user_id)?))
+            })
+        })
+        .await
+    }
+
+    // A parent takes its subtasks along; a subtask moved on its own becomes a
+    // top level task of the target list.
+    pub async fn move_task(
+        &self,
+        task_id: i64,
+        user_id: i64,
+        list_id: i64,
+    ) -> Result<i64, DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                move_to_list(connection, task_id, user_id, list_id)?;
+                bump_revision(connection, user_id)
+            })
+        })
+        .await
+    }
+
+    pub async fn update_task(
+        &self,
+        task_id: i64,
+        user_id: i64,
+        task: TaskInput,
+    ) -> Result<i64, DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                write_task(connection, task_id, user_id, task)?;
+                bump_revision(connection, user_id)
+            })
+        })
+        .await
+    }
+
+    // Applies an edit made of a move to a list, new fields and a change of
+    // `done`, all of it or nothing. `edit` works them out from the stored task
+    // inside the transaction, so that no other change lands in between; when it
+    // refuses the edit, nothing is written and its error is handed back.
+    pub async fn patch_task<E: Send + 'static>(
+        &self,
+        task_id: i64,
+        user_id: i64,
+        edit: impl FnOnce(&Task) -> Result<TaskEdit, E> + Send + 'static,
+        offset: UtcOffset,
+    ) -> Result<Result<i64, E>, DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                let current = select_task(connection, task_id, user_id)?;
+                let TaskEdit {
+                    list_id,
+                    task,
+                    done,
+                } = match edit(&current) {
+                    Ok(edit) => edit,
+                    Err(err) => return Ok(Err(err)),
+                };
+                if let Some(list_id) = list_id.filter(|list_id| *list_id != current.list_id) {
+                    move_to_list(connection, task_id, user_id, list_id)?;
+                }
+                write_task(connection, task_id, user_id, task)?;
+                if let Some(done) = done.filter(|done| *done != current.done) {
+                    mark_done(connection, task_id, user_id, done, offset)?;
+                }
+                bump_revision(connection, user_id).map(Ok)
+            })
+        })
+        .await
+    }
+
+    pub async fn delete_task(&self, task_id: i64, user_id: i64) -> Result<i64, DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                let task = select_task(connection, task_id, user_id)?;
+                execute(
+                    connection,
+                    "DELETE FROM task_tags WHERE task_id IN (
+                        SELECT task_id FROM tasks WHERE task_id = ?1 OR parent_id = ?1
+                    );",
+                    &[task_id.into()],
+                )?;
+                execute(
+                    connection,
+                    "DELETE FROM tasks_search WHERE rowid IN (
+                        SELECT task_id FROM tasks WHERE task_id = ?1 OR parent_id = ?1
+                    );",
+                    &[task_id.into()],
+                )?;
+                execute(
+                    connection,
+                    "DELETE FROM tasks WHERE task_id = ?1 OR parent_id = ?1;",
+                    &[task_id.into()],
+                )?;
+                roll_up(connection, task.parent_id)?;
+                bump_revision(connection, user_id)
+            })
+        })
+        .await
+    }
+
+    // Tasks of the user matching every word of `terms` as a prefix, best match
+    // first. Titles weigh more than descriptions.
+    pub async fn search_tasks(
+        &self,
+        user_id: i64,
+        terms: String,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, DbError> {
+        self.run(move |connection| {
+            let Some(fts_query) = fts_query(&terms) else {
+                return Ok(vec![]);
+            };
+            query(
+                connection,
+                "SELECT tasks.task_id, tasks.list_id, tasks.parent_id, tasks.done,
+                    highlight(tasks_search, 0, char(1), char(2)) AS title_match,
+                    snippet(tasks_search, 1, char(1), char(2), '…', 12) AS snippet,
+                    bm25(tasks_search, 10.0, 1.0) AS rank
+                FROM tasks_search JOIN tasks ON tasks.task_id = tasks_search.rowid
+                WHERE tasks_search MATCH ? AND tasks.user_id = ?
+                ORDER BY rank LIMIT ?;",
+                &[fts_query.into(), user_id.into(), limit.into()],
+            )
+        })
+        .await
+    }
+
+    pub async fn get_tags_by_user_id(&self, user_id: i64) -> Result<Vec<Tag>, DbError> {
+        self.run(move |connection| {
+            query(
+                connection,
+                "SELECT * from tags WHERE user_id = ? ORDER BY name;",
+                &[user_id.into()],
+            )
+        })
+        .await
+    }
+
+    pub async fn create_tag(&self, user_id: i64, name: String) -> Result<(), DbError> {
+        self.run(move |connection| {
+            execute(
+                connection,
+                "INSERT INTO tags (user_id, name) VALUES (?, ?);",
+                &[user_id.into(), name.into()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn rename_tag(
+        &self,
+        tag_id: i64,
+        user_id: i64,
+        name: String,
+    ) -> Result<i64, DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                let changed = execute(
+                    connection,
+                    "UPDATE tags SET name = ? WHERE tag_id = ? AND user_id = ?;",
+                    &[name.into(), tag_id.into(), user_id.into()],
+                )?;
+                if changed == 0 {
+                    return Err(DbError::TagNotFound);
+                }
+                bump_revision(connection, user_id)
+            })
+        })
+        .await
+    }
+
+    // Deleting a tag removes it from every task carrying it.
+    pub async fn delete_tag(&self, tag_id: i64, user_id: i64) -> Result<i64, DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                let changed = execute(
+                    connection,
+                    "DELETE FROM tags WHERE tag_id = ? AND user_id = ?;",
+                    &[tag_id.into(), user_id.into()],
+                )?;
+                if changed == 0 {
+                    return Err(DbError::TagNotFound);
+                }
+                execute(
+                    connection,
+                    "DELETE FROM task_tags WHERE tag_id = ?;",
+                    &[tag_id.into()],
+                )?;
+                bump_revision(connection, user_id)
+            })
+        })
+        .await
+    }
+
+    // Lists of a user with their number of open tasks.
+    pub async fn get_lists_by_user_id(&self, user_id: i64) -> Result<Vec<List>, DbError> {
+        self.run(move |connection| {
+            query(
+                connection,
+                "SELECT lists.list_id, lists.name,
+                    (SELECT COUNT(*) FROM tasks
+                    WHERE tasks.list_id = lists.list_id AND NOT tasks.done) AS open_tasks
+                FROM lists WHERE user_id = ? ORDER BY list_id;",
+                &[user_id.into()],
+            )
+        })
+        .await
+    }
+
+    pub async fn create_list(&self, user_id: i64, name: String) -> Result<(), DbError> {
+        self.run(move |connection| {
+            execute(
+                connection,
+                "INSERT INTO lists (user_id, name) VALUES (?, ?);",
+                &[user_id.into(), name.into()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn rename_list(
+        &self,
+        list_id: i64,
+        user_id: i64,
+        name: String,
+    ) -> Result<(), DbError> {
+        self.run(move |connection| {
+            let changed = execute(
+                connection,
+                "UPDATE lists SET name = ? WHERE list_id = ? AND user_id = ?;",
+                &[name.into(), list_id.into(), user_id.into()],
+            )?;
+            match changed {
+                0 => Err(DbError::ListNotFound),
+                _ => Ok(()),
+            }
+        })
+        .await
+    }
+
+    // Deleting a list deletes the tasks in it.
+    pub async fn delete_list(&self, list_id: i64, user_id: i64) -> Result<i64, DbError> {
+        self.run(move |connection| {
+            transaction(connection, || {
+                let changed = execute(
+                    connection,
+                    "DELETE FROM lists WHERE list_id = ? AND user_id = ?;",
+                    &[list_id.into(), user_id.into()],
+                )?;
+                if changed == 0 {
+                    return Err(DbError::ListNotFound);
+                }
+                execute(
+                    connection,
+                    "DELETE FROM task_tags WHERE task_id IN (
+                        SELECT task_id FROM tasks WHERE list_id = ?
+                    );",
+                    &[list_id.into()],
+                )?;
+                execute(
+                    connection,
+                    "DELETE FROM tasks_search WHERE rowid IN (
+                        SELECT task_id FROM tasks WHERE list_id = ?
+                    );",
+                    &[list_id.into()],
+                )?;
+                execute(
+                    connection,
+                    "DELETE FROM tasks WHERE list_id = ?;",
+                    &[list_id.into()],
+                )?;
+                // the last list deleted gives way to an empty "Inbox"
+                default_list(connection, user_id)?;
+                bump_revision(connection, user_id)
+            })
+        })
+        .await
+    }
+
+    // Adds the tasks, parents before their subtasks, in a single transaction
+    // that is rolled back unless `commit` is set, so that a dry run reports the
+    // same duplicates a real import would skip.
+    pub async fn import_tasks(
+        &self,
+        user_id: i64,
+        tasks: Vec<ImportTask>,
+        commit: bool,
+    ) -> Result<ImportReport, DbError> {
+        self.run(move |connection| {
+            let import = || {
+                let report = import_rows(connection, user_id, tasks)?;
+                if report.created > 0 || !report.lists_created.is_empty() {
+                    bump_revision(connection, user_id)?;
+                }
+                Ok(report)
+            };
+            match commit {
+                true => transaction(connection, import),
+                false => rolled_back(connection, import),
+            }
+        })
+        .await
+    }
+
+    // Runs `f` with a pooled connection on the blocking thread pool, keeping
+    // SQLite calls off the async workers.
+    async fn run<T, F>(&self, f: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&Connection) -> Result<T, DbError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        web::block(move || {
+            let connection = pool.get()?;
+            f(&connection)
+        })
+        .await
+        .map_err(|err| {
+            println!("{err}");
+            DbError::Unavailable
+        })?
+    }
+}
+
+// Counts a change to the user's tasks. Taken in the transaction making the
+// change, so that revisions follow the order changes are committed in.
+fn bump_revision(connection: &Connection, user_id: i64) -> Result<i64, DbError> {
+    scalar(
+        connection,
+        "UPDATE users SET revision = revision + 1 WHERE user_id = ? RETURNING revision;",
+        &[user_id.into()],
+    )
+    .map(Option::unwrap_or_default)
+}
+
+fn select_user_by_username(connection: &Connection, username: &str) -> Result<Vec<User>, DbError> {
+    query(
+        connection,
+        "SELECT * from users WHERE username = ? ;",
+        &[username.into()],
+    )
+}
+
+// App passwords are random, so their first characters single out the one to
+// check without giving much of them away.
+fn app_password_prefix(password: &str) -> String {
+    password.chars().take(8).collect()
+}
+
+fn select_task(connection: &Connection, task_id: i64, user_id: i64) -> Result<Task, DbError> {
+    let tasks: Vec<Task> = query(
+        connection,
+        "SELECT * from tasks WHERE task_id = ? AND user_id = ?;",
+        &[task_id.into(), user_id.into()],
+    )?;
+    tasks.into_iter().next().ok_or(DbError::TaskNotFound)
+}
+
+// A done task is completed at `completed_at`, or now when `None`.
+fn insert_task(
+    connection: &Connection,
+    user_id: i64,
+    list_id: Option<i64>,
+    parent_id: Option<i64>,
+    task: TaskInput,
+    done: bool,
+    completed_at: Option<String>,
+) -> Result<i64, DbError> {
+    let list_id = match (parent_id, list_id) {
+        (Some(parent_id), _) => {
+            let parent =
+                select_task(connection, parent_id, user_id).map_err(|_| DbError::ParentNotFound)?;
+            if parent.parent_id.is_some() {
+                return Err(DbError::NestedSubtask);
+            }
+            parent.list_id
         }
+        (None, Some(list_id)) => check_list(connection, list_id, user_id)?,
+        (None, None) => default_list(connection, user_id)?,
+    };
+    let title = task.title.clone();
+    execute(
+        connection,
+        "INSERT INTO tasks
+        (user_id, list_id, parent_id, position,
+        title, description, done, completed_at, due_at, start_at, priority, recurrence,
+        due_kind, start_kind)
+    VALUES (?1, ?8, ?9,
+        ?10,
+        ?2, ?3, ?4,
+        CASE WHEN ?4 THEN IFNULL(?14, strftime('%Y-%m-%dT%H:%M:%SZ', 'now')) END,
+        ?5, ?6, ?7, ?11,
+        ?12, ?13);",
+        &[
+            user_id.into(),
+            task.title.into(),
+            task.description.into(),
+            i64::from(done).into(),
+            task.due_at.into(),
+            task.start_at.into(),
+            task.priority.into(),
+            list_id.into(),
+            parent_id.into(),
+            end_position(connection, user_id, parent_id)?.into(),
+            task.recurrence.into(),
+            kind_column(task.due_kind.flatten()),
+            kind_column(task.start_kind.flatten()),
+            completed_at.into(),
+        ],
+    )
+    .map_err(|err| err.titled(&title))?;
+    let task_id = scalar(connection, "SELECT last_insert_rowid();", &[])?.unwrap_or_default();
+    if let Some(tags) = task.tags {
+        set_task_tags(connection, user_id, task_id, tags)?;
     }
+    index_task(connection, task_id)?;
+    roll_up(connection, parent_id)?;
+    Ok(task_id)
+}
 
-    pub fn delete_task(&self, task_id: i64, user_id: i64) -> bool {
-        let query = format!(
-            "DELETE FROM tasks WHERE task_id = {} AND user_id = {};",
-            task_id, user_id
-        );
-        //println!("{query}");
-        let result = &self.connection.execute(query);
-        match result {
-            Ok(_) => true,
-            Err(_) => false,
+fn move_to_list(
+    connection: &Connection,
+    task_id: i64,
+    user_id: i64,
+    list_id: i64,
+) -> Result<(), DbError> {
+    check_list(connection, list_id, user_id)?;
+    let task = select_task(connection, task_id, user_id)?;
+    let position = match task.parent_id {
+        Some(_) => end_position(connection, user_id, None)?,
+        None => task.position,
+    };
+    execute(
+        connection,
+        "UPDATE tasks SET list_id = ?, parent_id = NULL, position = ?
+        WHERE task_id = ?;",
+        &[list_id.into(), position.into(), task_id.into()],
+    )
+    .map_err(|err| err.titled(&task.title))?;
+    execute(
+        connection,
+        "UPDATE tasks SET list_id = ? WHERE parent_id = ?;",
+        &[list_id.into(), task_id.into()],
+    )?;
+    roll_up(connection, task.parent_id)
+}
+
+fn write_task(
+    connection: &Connection,
+    task_id: i64,
+    user_id: i64,
+    task: TaskInput,
+) -> Result<(), DbError> {
+    let title = task.title.clone();
+    let changed = execute(
+        connection,
+        "UPDATE tasks
+        SET title = ?1, description = ?2, due_at = ?3, start_at = ?4, priority = ?5,
+            recurrence = ?6,
+            due_kind = CASE WHEN ?9 THEN ?10 WHEN due_at IS ?3 THEN due_kind END,
+            start_kind = CASE WHEN ?11 THEN ?12 WHEN start_at IS ?4 THEN start_kind END
+        WHERE task_id = ?7 AND user_id = ?8;",
+        &[
+            task.title.into(),
+            task.description.into(),
+            task.due_at.into(),
+            task.start_at.into(),
+            task.priority.into(),
+            task.recurrence.into(),
+            task_id.into(),
+            user_id.into(),
+            i64::from(task.due_kind.is_some()).into(),
+            kind_column(task.due_kind.flatten()),
+            i64::from(task.start_kind.is_some()).into(),
+            kind_column(task.start_kind.flatten()),
+        ],
+    )
+    .map_err(|err| err.titled(&title))?;
+    if changed == 0 {
+        return Err(DbError::TaskNotFound);
+    }
+    if let Some(tags) = task.tags {
+        set_task_tags(connection, user_id, task_id, tags)?;
+    }
+    index_task(connection, task_id)
+}
+
+fn mark_done(
+    connection: &Connection,
+    task_id: i64,
+    user_id: i64,
+    done: bool,
+    offset: UtcOffset,
+) -> Result<(), DbError> {
+    let task = select_task(connection, task_id, user_id)?;
+    if done && !task.done {
+        if let Some(next) = recurrence::reschedule(&task, offset) {
+            return reschedule_task(connection, &task, next);
+        }
+    }
+    execute(
+        connection,
+        "UPDATE tasks
+        SET done = ?1,
+            completed_at = CASE
+                WHEN ?1 THEN IFNULL(completed_at, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+            END
+        WHERE task_id = ?2 OR (?1 AND parent_id = ?2);",
+        &[i64::from(done).into(), task_id.into()],
+    )?;
+    roll_up(connection, task.parent_id)
+}
+
+// Position after the last of the siblings a new task under `parent_id` gets.
+fn end_position(
+    connection: &Connection,
+    user_id: i64,
+    parent_id: Option<i64>,
+) -> Result<i64, DbError> {
+    let last = scalar(
+        connection,
+        "SELECT MAX(position) FROM tasks WHERE user_id = ? AND parent_id IS ?;",
+        &[user_id.into(), parent_id.into()],
+    )?;
+    Ok(last.unwrap_or_default() + POSITION_GAP)
+}
+
+// Spreads the given tasks out evenly in this order.
+fn renumber(connection: &Connection, order: impl IntoIterator<Item = i64>) -> Result<(), DbError> {
+    for (index, task_id) in order.into_iter().enumerate() {
+        execute(
+            connection,
+            "UPDATE tasks SET position = ? WHERE task_id = ?;",
+            &[((index as i64 + 1) * POSITION_GAP).into(), task_id.into()],
+        )?;
+    }
+    Ok(())
+}
+
+fn attach_tags(connection: &Connection, user_id: i64, tasks: &mut [Task]) -> Result<(), DbError> {
+    let task_tags: Vec<TaskTag> = query(
+        connection,
+        "SELECT task_tags.task_id, tags.name FROM task_tags
+        JOIN tags ON tags.tag_id = task_tags.tag_id
+        WHERE tags.user_id = ?
+        ORDER BY tags.name;",
+        &[user_id.into()],
+    )?;
+    for task in tasks.iter_mut() {
+        task.tags = task_tags
+            .iter()
+            .filter(|task_tag| task_tag.task_id == task.task_id)
+            .map(|task_tag| task_tag.name.clone())
+            .collect();
+    }
+    Ok(())
+}
+
+// Moves a task to the dates of its next occurrence and opens it again, along
+// with its subtasks, which repeat with it.
+fn reschedule_task(
+    connection: &Connection,
+    task: &Task,
+    next: recurrence::Rescheduled,
+) -> Result<(), DbError> {
+    execute(
+        connection,
+        "UPDATE tasks SET due_at = ?, start_at = ?, recurrence = ? WHERE task_id = ?;",
+        &[
+            next.due_at.into(),
+            next.start_at.into(),
+            next.recurrence.into(),
+            task.task_id.into(),
+        ],
+    )?;
+    execute(
+        connection,
+        "UPDATE tasks SET done = 0, completed_at = NULL WHERE task_id = ?1 OR parent_id = ?1;",
+        &[task.task_id.into()],
+    )?;
+    roll_up(connection, task.parent_id)
+}
+
+// Marks a parent done exactly when all of its subtasks are. A parent whose last
+// subtask went away keeps its state, and a recurring parent is not completed by
+// its subtasks, since completing it starts its next occurrence; that is left to
+// the user.
+fn roll_up(connection: &Connection, parent_id: Option<i64>) -> Result<(), DbError> {
+    let Some(parent_id) = parent_id else {
+        return Ok(());
+    };
+    execute(
+        connection,
+        "UPDATE tasks
+        SET done = children.all_done,
+            completed_at = CASE
+                WHEN children.all_done THEN IFNULL(completed_at, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+            END
+        FROM (SELECT MIN(done) AS all_done FROM tasks WHERE parent_id = ?1) AS children
+        WHERE task_id = ?1 AND children.all_done IS NOT NULL
+            AND NOT (children.all_done AND recurrence IS NOT NULL);",
+        &[parent_id.into()],
+    )?;
+    Ok(())
+}
+
+// Brings the search index entry of a task in line with its row, dropping it
+// when the task is gone.
+fn index_task(connection: &Connection, task_id: i64) -> Result<(), DbError> {
+    execute(
+        connection,
+        "DELETE FROM tasks_search WHERE rowid = ?;",
+        &[task_id.into()],
+    )?;
+    execute(
+        connection,
+        "INSERT INTO tasks_search (rowid, title, description)
+        SELECT task_id, title, IFNULL(description, '') FROM tasks WHERE task_id = ?;",
+        &[task_id.into()],
+    )?;
+    Ok(())
+}
+
+// Turns free text into an FTS5 query matching every word as a prefix. Words are
+// quoted so that FTS5 operators typed by the user are searched for literally.
+fn fts_query(terms: &str) -> Option<String> {
+    let words: Vec<String> = terms
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    if words.is_empty() {
+        None
+    } else {
+        Some(words.join(" "))
+    }
+}
+
+// Returns `list_id` if it is one of the user's lists.
+fn check_list(connection: &Connection, list_id: i64, user_id: i64) -> Result<i64, DbError> {
+    let lists: Vec<List> = query(
+        connection,
+        "SELECT list_id, name, 0 AS open_tasks FROM lists WHERE list_id = ? AND user_id = ?;",
+        &[list_id.into(), user_id.into()],
+    )?;
+    match lists.first() {
+        Some(list) => Ok(list.list_id),
+        None => Err(DbError::ListNotFound),
+    }
+}
+
+// The user's first list, creating an "Inbox" for users that have none.
+fn default_list(connection: &Connection, user_id: i64) -> Result<i64, DbError> {
+    execute(
+        connection,
+        "INSERT INTO lists (user_id, name)
+        SELECT ?1, 'Inbox' WHERE NOT EXISTS (SELECT 1 FROM lists WHERE user_id = ?1);",
+        &[user_id.into()],
+    )?;
+    let lists: Vec<List> = query(
+        connection,
+        "SELECT list_id, name, 0 AS open_tasks FROM lists
+        WHERE user_id = ? ORDER BY list_id LIMIT 1;",
+        &[user_id.into()],
+    )?;
+    Ok(lists[0].list_id)
+}
+
+// Replaces the tags of a task, creating the user's tags that do not exist yet.
+fn set_task_tags(
+    connection: &Connection,
+    user_id: i64,
+    task_id: i64,
+    tags: Vec<String>,
+) -> Result<(), DbError> {
+    execute(
+        connection,
+        "DELETE FROM task_tags WHERE task_id = ?;",
+        &[task_id.into()],
+    )?;
+    for name in tags {
+        execute(
+            connection,
+            "INSERT OR IGNORE INTO tags (user_id, name) VALUES (?, ?);",
+            &[user_id.into(), name.as_str().into()],
+        )?;
+        execute(
+            connection,
+            "INSERT OR IGNORE INTO task_tags (task_id, tag_id)
+            SELECT ?, tag_id FROM tags WHERE user_id = ? AND name = ?;",
+            &[task_id.into(), user_id.into(), name.into()],
+        )?;
+    }
+    Ok(())
+}
+
+// Imported tasks are matched to existing ones by title among their siblings, so
+// importing the same document twice creates nothing the second time. Subtasks
+// of a duplicate are added to the task it duplicates.
+fn import_rows(
+    connection: &Connection,
+    user_id: i64,
+    tasks: Vec<ImportTask>,
+) -> Result<ImportReport, DbError> {
+    let mut report = ImportReport::default();
+    // document key to the task id and list id it ended up as
+    let mut imported: HashMap<i64, (i64, i64)> = HashMap::new();
+    for task in tasks {
+        let (list_id, parent_id) = match (task.parent_key, &task.list) {
+            (Some(parent_key), _) => {
+                let (parent_id, list_id) = imported
+                    .get(&parent_key)
+                    .copied()
+                    .ok_or(DbError::Internal)?;
+                (list_id, Some(parent_id))
+            }
+            (None, Some(name)) => {
+                let lists: Vec<List> = query(
+                    connection,
+                    "SELECT list_id, name, 0 AS open_tasks FROM lists
+                    WHERE user_id = ? AND name = ?;",
+                    &[user_id.into(), name.as_str().into()],
+                )?;
+                let list_id = match lists.first() {
+                    Some(list) => list.list_id,
+                    None => {
+                        execute(
+                            connection,
+                            "INSERT INTO lists (user_id, name) VALUES (?, ?);",
+                            &[user_id.into(), name.as_str().into()],
+                        )?;
+                        report.lists_created.push(name.clone());
+                        scalar(connection, "SELECT last_insert_rowid();", &[])?.unwrap_or_default()
+                    }
+                };
+                (list_id, None)
+            }
+            (None, None) => (default_list(connection, user_id)?, None),
+        };
+        let existing = scalar(
+            connection,
+            "SELECT task_id FROM tasks
+            WHERE user_id = ? AND list_id = ? AND parent_id IS ? AND title = ?;",
+            &[
+                user_id.into(),
+                list_id.into(),
+                parent_id.into(),
+                task.task.title.as_str().into(),
+            ],
+        )?;
+        if let Some(task_id) = existing {
+            report.duplicates.push(ImportIssue {
+                row: task.row,
+                message: format!(
+                    "a task named '{}' already exists in this list",
+                    task.task.title
+                ),
+                title: task.task.title,
+            });
+            imported.insert(task.key, (task_id, list_id));
+            continue;
+        }
+        let task_id = insert_task(
+            connection,
+            user_id,
+            Some(list_id),
+            parent_id,
+            task.task,
+            task.done,
+            task.completed_at,
+        )?;
+        imported.insert(task.key, (task_id, list_id));
+        report.created += 1;
+    }
+    Ok(report)
+}
+
+// Runs `f` inside a transaction, committing on success and rolling back on error.
+fn transaction<T>(
+    connection: &Connection,
+    f: impl FnOnce() -> Result<T, DbError>,
+) -> Result<T, DbError> {
+    connection.execute("BEGIN IMMEDIATE;")?;
+    match f() {
+        Ok(value) => {
+            connection.execute("COMMIT;")?;
+            Ok(value)
         }
+        Err(err) => {
+            let _ = connection.execute("ROLLBACK;");
+            Err(err)
+        }
+    }
+}
+
+// Runs `f` inside a transaction that is always rolled back, to find out what
+// it would do.
+fn rolled_back<T>(
+    connection: &Connection,
+    f: impl FnOnce() -> Result<T, DbError>,
+) -> Result<T, DbError> {
+    connection.execute("BEGIN IMMEDIATE;")?;
+    let result = f();
+    let _ = connection.execute("ROLLBACK;");
+    result
+}
+
+// Runs a read query with positional parameters and maps every row.
+fn query<T: FromRow>(
+    connection: &Connection,
+    query: &str,
+    params: &[Value],
+) -> Result<Vec<T>, DbError> {
+    let mut statement = connection.prepare(query)?;
+    statement.bind(params)?;
+
+    let mut rows: Vec<T> = vec![];
+    while let State::Row = statement.next()? {
+        rows.push(T::from_row(&statement)?);
+    }
+    Ok(rows)
+}
+
+// Reads the first column of the first row as an integer, `None` for no rows or NULL.
+fn scalar(connection: &Connection, query: &str, params: &[Value]) -> Result<Option<i64>, DbError> {
+    let mut statement = connection.prepare(query)?;
+    statement.bind(params)?;
+    match statement.next()? {
+        State::Row => Ok(statement.read::<Option<i64>, _>(0)?),
+        State::Done => Ok(None),
+    }
+}
+
+// Runs a write query with positional parameters, stepping it to completion.
+// Returns the number of rows changed.
+fn execute(connection: &Connection, query: &str, params: &[Value]) -> Result<usize, DbError> {
+    let mut statement = connection.prepare(query)?;
+    statement.bind(params)?;
+    while statement.next()? == State::Row {}
+    Ok(connection.change_count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection() -> Connection {
+        let connection = sqlite::open(":memory:").unwrap();
+        migrations::run(&connection).unwrap();
+        connection
+            .execute("INSERT INTO users (user_id, username, password) VALUES (1, 'user', '');")
+            .unwrap();
+        connection
+    }
+
+    fn input(title: &str) -> TaskInput {
+        TaskInput {
+            title: title.to_string(),
+            description: String::new(),
+            due_at: None,
+            start_at: None,
+            priority: 0,
+            tags: None,
+            recurrence: None,
+            due_kind: None,
+            start_kind: None,
+        }
+    }
+
+    fn add(connection: &Connection, parent_id: Option<i64>, title: &str) -> Result<i64, DbError> {
+        insert_task(connection, 1, None, parent_id, input(title), false, None)
+    }
+
+    #[test]
+    fn sibling_title_conflicts_name_the_title() {
+        let connection = connection();
+        let milk = add(&connection, None, "Milk").unwrap();
+        let bread = add(&connection, None, "Bread").unwrap();
+
+        let err = add(&connection, None, "Milk").err().unwrap();
+        assert!(matches!(&err, DbError::TitleConflict(title) if title == "Milk"));
+        assert_eq!(
+            err.to_string(),
+            "a task named 'Milk' already exists in this list"
+        );
+
+        let err = write_task(&connection, bread, 1, input("Milk"))
+            .err()
+            .unwrap();
+        assert!(matches!(&err, DbError::TitleConflict(title) if title == "Milk"));
+
+        // subtasks only clash with the subtasks of the same parent
+        add(&connection, Some(milk), "Milk").unwrap();
+        add(&connection, Some(bread), "Milk").unwrap();
+        let err = add(&connection, Some(milk), "Milk").err().unwrap();
+        assert!(matches!(err, DbError::TitleConflict(title) if title == "Milk"));
+    }
+
+    #[test]
+    fn other_constraints_are_not_title_conflicts() {
+        let connection = connection();
+        let err = connection
+            .execute("INSERT INTO users (username, password) VALUES ('user', '');")
+            .map_err(DbError::from)
+            .err()
+            .unwrap();
+        assert!(matches!(err.titled("Milk"), DbError::UsernameConflict));
     }
 }
@@ -0,0 +1,160 @@
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
+    error::{JsonPayloadError, PathError, QueryPayloadError},
+    http::{
+        header::{HeaderName, HeaderValue},
+        StatusCode,
+    },
+    web, Error, HttpRequest, HttpResponse, ResponseError,
+};
+use serde::Serialize;
+use std::future::Future;
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// The body of every error answer. `code` is stable and meant for programs,
+// `message` for people.
+#[derive(Serialize)]
+pub struct ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+    pub request_id: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+pub fn error_response(
+    status_code: StatusCode,
+    code: &'static str,
+    message: String,
+    fields: Vec<FieldError>,
+) -> HttpResponse {
+    let request_id = REQUEST_ID.try_with(String::clone).unwrap_or_default();
+    if status_code.is_server_error() {
+        println!("request {request_id} failed: {code}: {message}");
+    }
+    HttpResponse::build(status_code).json(ErrorBody {
+        code,
+        message,
+        fields,
+        request_id,
+    })
+}
+
+// Gives every request an id, echoed in the `x-request-id` header and in error
+// bodies so a report can be matched with the server log.
+pub fn with_request_id<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+{
+    let request_id = uuid::Uuid::new_v4().to_string();
+    let header_value = HeaderValue::from_str(&request_id).ok();
+    let response = REQUEST_ID.scope(request_id, srv.call(req));
+    async move {
+        let mut response = response.await?;
+        if let Some(header_value) = header_value {
+            response
+                .headers_mut()
+                .insert(HeaderName::from_static(REQUEST_ID_HEADER), header_value);
+        }
+        Ok(response)
+    }
+}
+
+// Extractor failures, e.g. a body that is not valid JSON or lacks a field.
+#[derive(Debug)]
+pub enum RequestError {
+    Json(JsonPayloadError),
+    Query(QueryPayloadError),
+    Path(PathError),
+    // a body read as text, too large or not UTF-8
+    Body(Error),
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::Json(err) => write!(f, "{err}"),
+            RequestError::Query(err) => write!(f, "{err}"),
+            RequestError::Path(err) => write!(f, "{err}"),
+            RequestError::Body(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl RequestError {
+    fn code(&self) -> &'static str {
+        match self {
+            RequestError::Json(JsonPayloadError::ContentType) => "unsupported_media_type",
+            RequestError::Json(
+                JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. },
+            ) => "payload_too_large",
+            RequestError::Json(_) => "invalid_json",
+            RequestError::Query(_) => "invalid_query",
+            RequestError::Path(_) => "invalid_path",
+            RequestError::Body(err)
+                if err.as_response_error().status_code() == StatusCode::PAYLOAD_TOO_LARGE =>
+            {
+                "payload_too_large"
+            }
+            RequestError::Body(_) => "invalid_body",
+        }
+    }
+}
+
+impl ResponseError for RequestError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            RequestError::Json(JsonPayloadError::ContentType) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            RequestError::Json(err) => err.status_code(),
+            RequestError::Query(_) => StatusCode::BAD_REQUEST,
+            RequestError::Path(_) => StatusCode::NOT_FOUND,
+            RequestError::Body(err) => err.as_response_error().status_code(),
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        error_response(self.status_code(), self.code(), self.to_string(), vec![])
+    }
+}
+
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|err, _: &HttpRequest| RequestError::Json(err).into())
+}
+
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default()
+        .error_handler(|err, _: &HttpRequest| RequestError::Query(err).into())
+}
+
+pub fn path_config() -> web::PathConfig {
+    web::PathConfig::default().error_handler(|err, _: &HttpRequest| RequestError::Path(err).into())
+}
+
+// `PayloadConfig` takes no error handler, so handlers reading the body as a
+// `String` extract the result and pass it through here.
+pub fn text_body(body: Result<String, Error>) -> Result<String, RequestError> {
+    body.map_err(RequestError::Body)
+}
@@ -0,0 +1,98 @@
+use actix_web::{
+    delete, get,
+    http::header,
+    post,
+    web::{self, Data, Json},
+    HttpRequest, HttpResponse, Result,
+};
+use serde::Serialize;
+
+use crate::auth::CurrentUser;
+use crate::db::{DbError, TaskFilter, TaskSort, UserTasksDB};
+use crate::ical;
+
+// Where the user's calendar feed can be subscribed to, if it is enabled.
+#[derive(Serialize)]
+struct FeedInfo {
+    url: Option<String>,
+}
+
+fn feed_info(req: &HttpRequest, token: Option<String>) -> FeedInfo {
+    let connection = req.connection_info();
+    FeedInfo {
+        url: token.map(|token| {
+            format!(
+                "{}://{}/feed/{token}.ics",
+                connection.scheme(),
+                connection.host()
+            )
+        }),
+    }
+}
+
+#[get("/feed")]
+async fn feed_get(
+    user_tasks_db: Data<UserTasksDB>,
+    req: HttpRequest,
+    user: CurrentUser,
+) -> Result<Json<FeedInfo>> {
+    let token = user_tasks_db
+        .get_user(user.user_id)
+        .await?
+        .and_then(|user| user.feed_token);
+    Ok(Json(feed_info(&req, token)))
+}
+
+// Turns the feed on, or moves it to a new URL so the old one stops working.
+#[post("/feed")]
+async fn feed_reset(
+    user_tasks_db: Data<UserTasksDB>,
+    req: HttpRequest,
+    user: CurrentUser,
+) -> Result<Json<FeedInfo>> {
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    user_tasks_db
+        .set_feed_token(user.user_id, Some(token.clone()))
+        .await?;
+    Ok(Json(feed_info(&req, Some(token))))
+}
+
+#[delete("/feed")]
+async fn feed_disable(
+    user_tasks_db: Data<UserTasksDB>,
+    req: HttpRequest,
+    user: CurrentUser,
+) -> Result<Json<FeedInfo>> {
+    user_tasks_db.set_feed_token(user.user_id, None).await?;
+    Ok(Json(feed_info(&req, None)))
+}
+
+// The feed itself, for calendar apps: the token in the path stands in for the
+// session cookie.
+#[get("/feed/{token}.ics")]
+async fn feed_calendar(
+    user_tasks_db: Data<UserTasksDB>,
+    token: web::Path<String>,
+) -> Result<HttpResponse> {
+    let Some(user) = user_tasks_db
+        .get_user_by_feed_token(token.into_inner())
+        .await?
+    else {
+        return Err(DbError::FeedNotFound.into());
+    };
+    let tasks = user_tasks_db
+        .get_tasks_by_user_id(user.user_id, TaskSort::default(), TaskFilter::default())
+        .await?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "inline; filename=\"tasks.ics\"",
+        ))
+        .body(ical::calendar(
+            Some(&format!("Tasks of {}", user.username)),
+            &tasks,
+            &tasks,
+        )))
+}
@@ -0,0 +1,797 @@
+use std::collections::HashMap;
+
+use time::{
+    macros::format_description, Date, Month, OffsetDateTime, PrimitiveDateTime, UtcOffset, Weekday,
+};
+
+use crate::db::{DateKind, Task};
+use crate::schedule;
+
+// Lines of an iCalendar file are at most 75 octets long, CRLF not included.
+const LINE_OCTETS: usize = 75;
+
+// The iCalendar UID of a task, stable for as long as the task exists.
+pub fn uid(task: &Task) -> String {
+    task.dav_uid
+        .clone()
+        .unwrap_or_else(|| format!("task-{}@rustodo", task.task_id))
+}
+
+// The name of a task's resource within its CalDAV collection.
+pub fn resource_name(task: &Task) -> String {
+    task.dav_name
+        .clone()
+        .unwrap_or_else(|| format!("task-{}.ics", task.task_id))
+}
+
+// Stored dates are UTC RFC 3339 like 2026-10-19T07:00:00Z, which only lose
+// their separators in iCalendar.
+fn date_time(value: &str) -> String {
+    value.replace(['-', ':'], "")
+}
+
+// A DTSTART or DUE value, with its parameters, in the form the client wrote it.
+fn date_value(value: &str, kind: Option<DateKind>) -> String {
+    let value = date_time(value);
+    match kind {
+        Some(DateKind::Date) => format!(";VALUE=DATE:{}", &value[..8]),
+        Some(DateKind::Floating) => format!(":{}", value.trim_end_matches('Z')),
+        None => format!(":{value}"),
+    }
+}
+
+// Escapes a TEXT value (RFC 5545, 3.3.11).
+fn text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Splits a content line into CRLF-terminated lines of at most 75 octets,
+// continuation lines starting with a space, without breaking up characters.
+fn fold(line: &str, out: &mut String) {
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > LINE_OCTETS {
+            out.push_str("\r\n ");
+            octets = 1;
+        }
+        out.push(c);
+        octets += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+// The properties of a task's VTODO, DTSTAMP left out. `tasks` is searched for
+// the task's parent.
+fn vtodo(task: &Task, tasks: &[Task]) -> Vec<String> {
+    let mut lines = vec![
+        format!("UID:{}", uid(task)),
+        format!("SUMMARY:{}", text(&task.title)),
+    ];
+    if !task.description.is_empty() {
+        lines.push(format!("DESCRIPTION:{}", text(&task.description)));
+    }
+    if let Some(start_at) = &task.start_at {
+        lines.push(format!("DTSTART{}", date_value(start_at, task.start_kind)));
+    }
+    if let Some(due_at) = &task.due_at {
+        lines.push(format!("DUE{}", date_value(due_at, task.due_kind)));
+    }
+    if task.done {
+        lines.push("STATUS:COMPLETED".to_string());
+        if let Some(completed_at) = &task.completed_at {
+            lines.push(format!("COMPLETED:{}", date_time(completed_at)));
+        }
+    } else {
+        lines.push("STATUS:NEEDS-ACTION".to_string());
+    }
+    // iCalendar counts from 1 (highest) to 9 (lowest), 0 being undefined
+    let priority = match task.priority {
+        3 => 1,
+        2 => 5,
+        1 => 9,
+        _ => 0,
+    };
+    if priority != 0 {
+        lines.push(format!("PRIORITY:{priority}"));
+    }
+    if !task.tags.is_empty() {
+        let tags: Vec<String> = task.tags.iter().map(|tag| text(tag)).collect();
+        lines.push(format!("CATEGORIES:{}", tags.join(",")));
+    }
+    if let Some(parent) = tasks
+        .iter()
+        .find(|parent| Some(parent.task_id) == task.parent_id)
+    {
+        lines.push(format!("RELATED-TO:{}", uid(parent)));
+    }
+    if let Some(recurrence) = &task.recurrence {
+        lines.push(format!("RRULE:{recurrence}"));
+    }
+    lines
+}
+
+// A VCALENDAR holding one VTODO per task in `tasks`; `name` labels it in
+// calendar apps when given.
+pub fn calendar(name: Option<&str>, tasks: &[Task], all: &[Task]) -> String {
+    let stamp = date_time(&schedule::format(OffsetDateTime::now_utc()));
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//rustodo//tasks//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    if let Some(name) = name {
+        lines.push(format!("X-WR-CALNAME:{}", text(name)));
+    }
+    for task in tasks {
+        lines.push("BEGIN:VTODO".to_string());
+        lines.push(format!("DTSTAMP:{stamp}"));
+        lines.extend(vtodo(task, all));
+        lines.push("END:VTODO".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut out = String::new();
+    for line in lines {
+        fold(&line, &mut out);
+    }
+    out
+}
+
+// Changes whenever anything a client sees of the task changes. FNV-1a keeps it
+// the same across builds, unlike std's hasher.
+pub fn etag(task: &Task, tasks: &[Task]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in vtodo(task, tasks).join("\n").bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("\"{hash:016x}\"")
+}
+
+// What a client sent in a VTODO, in the shape `TaskInfo` takes. Properties
+// without a counterpart on tasks are dropped.
+pub struct Vtodo {
+    pub uid: String,
+    pub summary: String,
+    pub description: String,
+    pub start_at: Option<String>,
+    pub start_kind: Option<DateKind>,
+    pub due_at: Option<String>,
+    pub due_kind: Option<DateKind>,
+    pub completed: bool,
+    pub priority: i64,
+    pub categories: Vec<String>,
+    // UID of the parent task
+    pub related_to: Option<String>,
+    pub rrule: Option<String>,
+}
+
+// Reverses `text`.
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n' | 'N') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => {}
+            },
+            (c, false) => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+// Splits a list value at the commas that are not escaped.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut escaped = false;
+    for c in value.chars() {
+        match c {
+            ',' if !escaped => items.push(String::new()),
+            _ => items.last_mut().unwrap().push(c),
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    items.iter().map(|item| unescape(item)).collect()
+}
+
+// A content line, unfolded, with its name in upper case and its parameters.
+struct ContentLine<'a> {
+    name: String,
+    params: Vec<(String, &'a str)>,
+    value: &'a str,
+}
+
+impl ContentLine<'_> {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+fn unfold(data: &str) -> String {
+    data.replace("\r\n", "\n")
+        .replace("\n ", "")
+        .replace("\n\t", "")
+}
+
+fn content_lines(unfolded: &str) -> Result<Vec<ContentLine<'_>>, String> {
+    let mut lines = vec![];
+    for line in unfolded.lines().filter(|line| !line.is_empty()) {
+        // parameters end at the first colon outside quotes, and are separated
+        // by semicolons outside quotes
+        let mut quoted = false;
+        let mut separators = vec![];
+        let mut colon = None;
+        for (i, c) in line.char_indices() {
+            match c {
+                '"' => quoted = !quoted,
+                ';' if !quoted => separators.push(i),
+                ':' if !quoted => {
+                    colon = Some(i);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let Some(colon) = colon else {
+            return Err(format!("'{line}' is not an iCalendar content line"));
+        };
+        let mut bounds = separators;
+        bounds.push(colon);
+        let name = line[..bounds[0]].to_ascii_uppercase();
+        let params = bounds
+            .windows(2)
+            .filter_map(|window| {
+                let (name, value) = line[window[0] + 1..window[1]].split_once('=')?;
+                Some((name.to_ascii_uppercase(), value.trim_matches('"')))
+            })
+            .collect();
+        lines.push(ContentLine {
+            name,
+            params,
+            value: &line[colon + 1..],
+        });
+    }
+    Ok(lines)
+}
+
+// A DATE or DATE-TIME value as a local time, with whether it is a date and
+// whether it is UTC.
+fn parse_date_time(value: &str) -> Result<(PrimitiveDateTime, bool, bool), String> {
+    let date_time = format_description!("[year][month][day]T[hour][minute][second]");
+    let date = format_description!("[year][month][day]");
+    let invalid = || format!("'{value}' is not an iCalendar date");
+    if value.len() == 8 {
+        let date = Date::parse(value, date).map_err(|_| invalid())?;
+        return Ok((date.midnight(), true, false));
+    }
+    let (value, utc) = match value.strip_suffix('Z') {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+    let date_time = PrimitiveDateTime::parse(value, date_time).map_err(|_| invalid())?;
+    Ok((date_time, false, utc))
+}
+
+// How a VTIMEZONE yearly rule places its onsets: in a month, on the nth (or
+// nth last, when negative; any, when 0) weekday, optionally within given days
+// of the month.
+struct YearlyRule {
+    month: Month,
+    weekday: Option<(i8, Weekday)>,
+    month_days: Vec<u8>,
+    until: Option<PrimitiveDateTime>,
+}
+
+const WEEKDAYS: [(&str, Weekday); 7] = [
+    ("MO", Weekday::Monday),
+    ("TU", Weekday::Tuesday),
+    ("WE", Weekday::Wednesday),
+    ("TH", Weekday::Thursday),
+    ("FR", Weekday::Friday),
+    ("SA", Weekday::Saturday),
+    ("SU", Weekday::Sunday),
+];
+
+// Only the yearly rules time zones are described with are understood.
+fn yearly_rule(value: &str) -> Option<YearlyRule> {
+    let mut rule = YearlyRule {
+        month: Month::January,
+        weekday: None,
+        month_days: vec![],
+        until: None,
+    };
+    let mut yearly = false;
+    let mut month = false;
+    for part in value.split(';') {
+        let (name, value) = part.split_once('=')?;
+        match name.to_ascii_uppercase().as_str() {
+            "FREQ" => yearly = value.eq_ignore_ascii_case("YEARLY"),
+            "BYMONTH" => {
+                rule.month = Month::try_from(value.parse::<u8>().ok()?).ok()?;
+                month = true;
+            }
+            "BYDAY" => {
+                let split = value.len().checked_sub(2)?;
+                let (ordinal, day) = (value.get(..split)?, value.get(split..)?);
+                let ordinal = match ordinal {
+                    "" => 0,
+                    ordinal => ordinal.trim_start_matches('+').parse().ok()?,
+                };
+                let weekday = WEEKDAYS
+                    .iter()
+                    .find(|(code, _)| code.eq_ignore_ascii_case(day))?
+                    .1;
+                rule.weekday = Some((ordinal, weekday));
+            }
+            "BYMONTHDAY" => {
+                rule.month_days = value
+                    .split(',')
+                    .map(|day| day.parse().ok())
+                    .collect::<Option<_>>()?
+            }
+            "UNTIL" => rule.until = Some(parse_date_time(value).ok()?.0),
+            _ => return None,
+        }
+    }
+    (yearly && month).then_some(rule)
+}
+
+// A STANDARD or DAYLIGHT part of a VTIMEZONE.
+struct Observance {
+    start: PrimitiveDateTime,
+    offset_from: UtcOffset,
+    offset_to: UtcOffset,
+    rule: Option<YearlyRule>,
+    dates: Vec<PrimitiveDateTime>,
+}
+
+fn utc_offset(value: &str) -> Option<UtcOffset> {
+    let sign = match value.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let digits = |range: std::ops::Range<usize>| value.get(range)?.parse::<i8>().ok();
+    UtcOffset::from_hms(
+        sign * digits(1..3)?,
+        sign * digits(3..5)?,
+        sign * digits(5..7).unwrap_or(0),
+    )
+    .ok()
+}
+
+impl Observance {
+    fn onset(&self, rule: &YearlyRule, year: i32) -> Option<PrimitiveDateTime> {
+        let days = (1..=rule.month.length(year))
+            .filter_map(|day| Date::from_calendar_date(year, rule.month, day).ok());
+        let date = match rule.weekday {
+            Some((ordinal, weekday)) => {
+                let matching: Vec<Date> = days
+                    .filter(|date| {
+                        date.weekday() == weekday
+                            && (rule.month_days.is_empty() || rule.month_days.contains(&date.day()))
+                    })
+                    .collect();
+                match ordinal {
+                    0 => matching.first(),
+                    1.. => matching.get(ordinal as usize - 1),
+                    _ => matching
+                        .len()
+                        .checked_sub(ordinal.unsigned_abs() as usize)
+                        .and_then(|index| matching.get(index)),
+                }
+                .copied()
+            }
+            None => {
+                let day = rule.month_days.first().copied().unwrap_or(self.start.day());
+                Date::from_calendar_date(year, rule.month, day).ok()
+            }
+        }?;
+        Some(date.with_time(self.start.time()))
+    }
+
+    // When this observance last took effect at or before `local`.
+    fn latest_onset(&self, local: PrimitiveDateTime) -> Option<PrimitiveDateTime> {
+        let mut onsets = vec![self.start];
+        onsets.extend(&self.dates);
+        if let Some(rule) = &self.rule {
+            for year in [local.year() - 1, local.year()] {
+                onsets.extend(
+                    self.onset(rule, year)
+                        .filter(|onset| rule.until.is_none_or(|until| *onset <= until)),
+                );
+            }
+        }
+        onsets
+            .into_iter()
+            .filter(|onset| *onset >= self.start && *onset <= local)
+            .max()
+    }
+}
+
+// The time zones a calendar object defines, by TZID. Time zones using rules
+// beyond the usual yearly ones are left out.
+fn timezones(lines: &[ContentLine]) -> HashMap<String, Vec<Observance>> {
+    let mut timezones = HashMap::new();
+    let mut tzid = None;
+    let mut observances = Some(vec![]);
+    let mut observance: Option<Observance> = None;
+    let mut inside = false;
+    for line in lines {
+        match (line.name.as_str(), line.value.to_ascii_uppercase().as_str()) {
+            ("BEGIN", "VTIMEZONE") => {
+                inside = true;
+                tzid = None;
+                observances = Some(vec![]);
+            }
+            ("END", "VTIMEZONE") => {
+                inside = false;
+                if let (Some(tzid), Some(observances)) = (tzid.take(), observances.take()) {
+                    if !observances.is_empty() {
+                        timezones.insert(tzid, observances);
+                    }
+                }
+            }
+            ("BEGIN", "STANDARD" | "DAYLIGHT") if inside => {
+                observance = Some(Observance {
+                    start: PrimitiveDateTime::MIN,
+                    offset_from: UtcOffset::UTC,
+                    offset_to: UtcOffset::UTC,
+                    rule: None,
+                    dates: vec![],
+                });
+            }
+            ("END", "STANDARD" | "DAYLIGHT") if inside => {
+                if let (Some(observances), Some(observance)) = (&mut observances, observance.take())
+                {
+                    observances.push(observance);
+                }
+            }
+            ("TZID", _) if inside && observance.is_none() => tzid = Some(line.value.to_string()),
+            (name, _) if inside => {
+                let Some(current) = &mut observance else {
+                    continue;
+                };
+                let understood = match name {
+                    "DTSTART" => parse_date_time(line.value)
+                        .map(|(start, _, _)| current.start = start)
+                        .is_ok(),
+                    "TZOFFSETFROM" => utc_offset(line.value)
+                        .map(|offset| current.offset_from = offset)
+                        .is_some(),
+                    "TZOFFSETTO" => utc_offset(line.value)
+                        .map(|offset| current.offset_to = offset)
+                        .is_some(),
+                    "RRULE" => yearly_rule(line.value)
+                        .map(|rule| current.rule = Some(rule))
+                        .is_some(),
+                    "RDATE" => line.value.split(',').all(|value| {
+                        parse_date_time(value)
+                            .map(|(date, _, _)| current.dates.push(date))
+                            .is_ok()
+                    }),
+                    _ => true,
+                };
+                if !understood {
+                    observances = None;
+                }
+            }
+            _ => {}
+        }
+    }
+    timezones
+}
+
+// The UTC offset in effect at a local time: that of the observance that took
+// effect last, or before the first one the offset it started from.
+fn resolve(observances: &[Observance], local: PrimitiveDateTime) -> UtcOffset {
+    observances
+        .iter()
+        .filter_map(|observance| Some((observance.latest_onset(local)?, observance.offset_to)))
+        .max_by_key(|(onset, _)| *onset)
+        .map(|(_, offset)| offset)
+        .or_else(|| {
+            observances
+                .iter()
+                .min_by_key(|observance| observance.start)
+                .map(|observance| observance.offset_from)
+        })
+        .unwrap_or(UtcOffset::UTC)
+}
+
+// A DTSTART or DUE as RFC 3339 UTC. Times with a TZID are converted through the
+// VTIMEZONE of that name; dates, floating times and times in a time zone the
+// object does not describe are kept as they read, see `DateKind`.
+fn date_property(
+    line: &ContentLine,
+    timezones: &HashMap<String, Vec<Observance>>,
+) -> Result<(String, Option<DateKind>), String> {
+    let (local, date, utc) = parse_date_time(line.value)?;
+    let kind = match (
+        date || line
+            .param("VALUE")
+            .is_some_and(|value| value.eq_ignore_ascii_case("DATE")),
+        utc,
+    ) {
+        (true, _) => Some(DateKind::Date),
+        (false, true) => None,
+        (false, false) => match line.param("TZID").and_then(|tzid| timezones.get(tzid)) {
+            Some(observances) => {
+                let utc = local.assume_offset(resolve(observances, local));
+                return Ok((schedule::format(utc), None));
+            }
+            None => Some(DateKind::Floating),
+        },
+    };
+    Ok((schedule::format(local.assume_utc()), kind))
+}
+
+// Reads the first VTODO of a calendar object, skipping nested components
+// such as alarms.
+pub fn parse_vtodo(data: &str) -> Result<Vtodo, String> {
+    let unfolded = unfold(data);
+    let lines = content_lines(&unfolded)?;
+    let timezones = timezones(&lines);
+    let mut vtodo = Vtodo {
+        uid: String::new(),
+        summary: String::new(),
+        description: String::new(),
+        start_at: None,
+        start_kind: None,
+        due_at: None,
+        due_kind: None,
+        completed: false,
+        priority: 0,
+        categories: vec![],
+        related_to: None,
+        rrule: None,
+    };
+    // components entered within the VTODO, which itself counts as one
+    let mut depth = 0;
+    let mut found = false;
+    for line in &lines {
+        let value = line.value;
+        match (line.name.as_str(), depth) {
+            ("BEGIN", 0) if value.eq_ignore_ascii_case("VTODO") && !found => {
+                depth = 1;
+                found = true;
+            }
+            ("BEGIN", 1..) => depth += 1,
+            ("END", 1..) => depth -= 1,
+            (name, 1) => match name {
+                "UID" => vtodo.uid = value.to_string(),
+                "SUMMARY" => vtodo.summary = unescape(value),
+                "DESCRIPTION" => vtodo.description = unescape(value),
+                "DTSTART" => {
+                    let (start_at, kind) = date_property(line, &timezones)?;
+                    vtodo.start_at = Some(start_at);
+                    vtodo.start_kind = kind;
+                }
+                "DUE" => {
+                    let (due_at, kind) = date_property(line, &timezones)?;
+                    vtodo.due_at = Some(due_at);
+                    vtodo.due_kind = kind;
+                }
+                "STATUS" => vtodo.completed = value.eq_ignore_ascii_case("COMPLETED"),
+                "PRIORITY" => {
+                    vtodo.priority = match value.trim().parse::<u8>() {
+                        Ok(1..=4) => 3,
+                        Ok(5) => 2,
+                        Ok(6..=9) => 1,
+                        _ => 0,
+                    }
+                }
+                "CATEGORIES" => vtodo.categories.extend(
+                    split_list(value)
+                        .into_iter()
+                        .filter(|category| !category.trim().is_empty()),
+                ),
+                // other relations, such as RELTYPE=CHILD, have no counterpart
+                "RELATED-TO"
+                    if line
+                        .param("RELTYPE")
+                        .is_none_or(|reltype| reltype.eq_ignore_ascii_case("PARENT")) =>
+                {
+                    vtodo.related_to = Some(value.to_string())
+                }
+                "RRULE" => vtodo.rrule = Some(value.to_string()),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    if !found {
+        return Err("the calendar object holds no VTODO".into());
+    }
+    if vtodo.uid.is_empty() {
+        return Err("the VTODO has no UID".into());
+    }
+    Ok(vtodo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BERLIN: &str = "BEGIN:VTIMEZONE\r
+TZID:Europe/Berlin\r
+BEGIN:DAYLIGHT\r
+TZOFFSETFROM:+0100\r
+TZOFFSETTO:+0200\r
+DTSTART:19810329T020000\r
+RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r
+END:DAYLIGHT\r
+BEGIN:STANDARD\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+DTSTART:19961027T030000\r
+RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r
+END:STANDARD\r
+END:VTIMEZONE\r
+";
+
+    fn calendar(timezones: &str, properties: &str) -> String {
+        format!(
+            "BEGIN:VCALENDAR\r\n{timezones}BEGIN:VTODO\r\nUID:task-1\r\n{properties}END:VTODO\r\nEND:VCALENDAR\r\n"
+        )
+    }
+
+    #[test]
+    fn fold_keeps_lines_within_75_octets_and_characters_whole() {
+        let line = format!("SUMMARY:{}", "ä".repeat(60));
+        let mut out = String::new();
+        fold(&line, &mut out);
+        assert!(out.split("\r\n").all(|part| part.len() <= LINE_OCTETS));
+        assert_eq!(out.replace("\r\n ", "").trim_end(), line);
+    }
+
+    #[test]
+    fn parses_text_lists_and_relations() {
+        let vtodo = parse_vtodo(&calendar(
+            "",
+            "SUMMARY:Buy milk\\, eggs\r\n\
+             DESCRIPTION:first\\nsecond\r\n\
+             CATEGORIES:home,shop\\,ping\r\n\
+             PRIORITY:1\r\n\
+             STATUS:COMPLETED\r\n\
+             RELATED-TO;RELTYPE=CHILD:task-3\r\n\
+             RELATED-TO:task-2\r\n\
+             BEGIN:VALARM\r\n\
+             UID:alarm\r\n\
+             END:VALARM\r\n",
+        ))
+        .unwrap();
+        assert_eq!(vtodo.uid, "task-1");
+        assert_eq!(vtodo.summary, "Buy milk, eggs");
+        assert_eq!(vtodo.description, "first\nsecond");
+        assert_eq!(vtodo.categories, ["home", "shop,ping"]);
+        assert_eq!(vtodo.priority, 3);
+        assert!(vtodo.completed);
+        assert_eq!(vtodo.related_to.as_deref(), Some("task-2"));
+    }
+
+    #[test]
+    fn unfolds_continuation_lines() {
+        let vtodo = parse_vtodo(&calendar("", "SUMMARY:Buy\r\n  milk\r\n\tnow\r\n")).unwrap();
+        assert_eq!(vtodo.summary, "Buy milknow");
+    }
+
+    #[test]
+    fn rejects_objects_without_a_vtodo_or_uid() {
+        assert!(parse_vtodo("BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n").is_err());
+        assert!(parse_vtodo("BEGIN:VTODO\r\nSUMMARY:x\r\nEND:VTODO\r\n").is_err());
+        assert!(parse_vtodo(&calendar("", "no colon\r\n")).is_err());
+        assert!(parse_vtodo(&calendar("", "DUE:2026-10-20\r\n")).is_err());
+    }
+
+    #[test]
+    fn reads_dates_as_utc() {
+        let vtodo = parse_vtodo(&calendar(
+            "",
+            "DTSTART;VALUE=DATE:20261019\r\nDUE:20261020T100000Z\r\n",
+        ))
+        .unwrap();
+        assert_eq!(vtodo.start_at.as_deref(), Some("2026-10-19T00:00:00Z"));
+        assert_eq!(vtodo.due_at.as_deref(), Some("2026-10-20T10:00:00Z"));
+        assert_eq!(date_time("2026-10-20T10:00:00Z"), "20261020T100000Z");
+    }
+
+    #[test]
+    fn keeps_dates_and_floating_times_as_written() {
+        let vtodo = parse_vtodo(&calendar(
+            "",
+            "DTSTART;VALUE=DATE:20261019\r\nDUE:20261020T100000\r\n",
+        ))
+        .unwrap();
+        assert_eq!(vtodo.start_at.as_deref(), Some("2026-10-19T00:00:00Z"));
+        assert_eq!(vtodo.start_kind, Some(DateKind::Date));
+        assert_eq!(vtodo.due_at.as_deref(), Some("2026-10-20T10:00:00Z"));
+        assert_eq!(vtodo.due_kind, Some(DateKind::Floating));
+        assert_eq!(
+            date_value("2026-10-19T00:00:00Z", Some(DateKind::Date)),
+            ";VALUE=DATE:20261019"
+        );
+        assert_eq!(
+            date_value("2026-10-20T10:00:00Z", Some(DateKind::Floating)),
+            ":20261020T100000"
+        );
+        assert_eq!(
+            date_value("2026-10-20T10:00:00Z", None),
+            ":20261020T100000Z"
+        );
+    }
+
+    #[test]
+    fn converts_times_in_a_described_time_zone() {
+        let vtodo = parse_vtodo(&calendar(
+            BERLIN,
+            "DTSTART;TZID=Europe/Berlin:20261020T100000\r\n\
+             DUE;TZID=\"Europe/Berlin\":20261101T100000\r\n",
+        ))
+        .unwrap();
+        assert_eq!(vtodo.start_at.as_deref(), Some("2026-10-20T08:00:00Z"));
+        assert_eq!(vtodo.start_kind, None);
+        assert_eq!(vtodo.due_at.as_deref(), Some("2026-11-01T09:00:00Z"));
+        assert_eq!(vtodo.due_kind, None);
+    }
+
+    #[test]
+    fn switches_offsets_at_the_onsets() {
+        let lines = content_lines(BERLIN).unwrap();
+        let timezones = timezones(&lines);
+        let berlin = &timezones["Europe/Berlin"];
+        let local = |value| parse_date_time(value).unwrap().0;
+        // 2026 switches on March 29 and October 25
+        assert_eq!(resolve(berlin, local("20260329T015959")).whole_hours(), 1);
+        assert_eq!(resolve(berlin, local("20260329T030000")).whole_hours(), 2);
+        assert_eq!(resolve(berlin, local("20261025T025959")).whole_hours(), 2);
+        assert_eq!(resolve(berlin, local("20261025T030000")).whole_hours(), 1);
+        // before the first observance, its starting offset
+        assert_eq!(resolve(berlin, local("19700101T000000")).whole_hours(), 1);
+    }
+
+    #[test]
+    fn time_zones_with_unreadable_rules_are_left_out() {
+        for rule in [
+            "BYDAY=€",
+            "BYDAY=1€",
+            "BYDAY=€SU",
+            "BYMONTH=13",
+            "FREQ=MONTHLY",
+        ] {
+            let berlin = BERLIN.replace("BYDAY=-1SU", rule);
+            let lines = content_lines(&berlin).unwrap();
+            assert!(timezones(&lines).is_empty(), "{rule}");
+        }
+    }
+
+    #[test]
+    fn times_in_an_unknown_time_zone_are_floating() {
+        let vtodo = parse_vtodo(&calendar(
+            "",
+            "DUE;TZID=America/New_York:20261020T100000\r\n",
+        ))
+        .unwrap();
+        assert_eq!(vtodo.due_at.as_deref(), Some("2026-10-20T10:00:00Z"));
+        assert_eq!(vtodo.due_kind, Some(DateKind::Floating));
+    }
+}
@@ -1,22 +1,41 @@
-use std::{fs, path::PathBuf};
-
 use actix_cors::Cors;
-use actix_files::NamedFile;
 use actix_session::{storage::CookieSessionStore, Session, SessionMiddleware};
 use actix_web::{
-    cookie::Key,
     delete, get,
-    http::StatusCode,
+    http::{header, StatusCode},
     post, put,
     web::{self, Data, Json},
-    App, HttpRequest, HttpServer, Responder, Result,
+    App, HttpResponse, HttpServer, Responder, ResponseError, Result,
 };
 
-mod conf;
+mod auth;
+mod dav;
 mod db;
-use db::UserTasksDB;
+mod error;
+mod feed;
+mod ical;
+mod migrations;
+mod password;
+mod pool;
+mod recurrence;
+mod schedule;
+mod settings;
+mod tasks;
+mod transfer;
+mod validate;
+use auth::{AuthError, CurrentUser, OptionalUser};
+use db::{
+    DbError, List, SearchHit, SortKey, SortOrder, Tag, Task, TaskFilter, TaskInput, TaskSort,
+    UserTasksDB,
+};
+use error::FieldError;
+use recurrence::Recurrence;
+use schedule::TaskGroups;
+use settings::Settings;
+use validate::{Charset, Rule};
 
 use serde::{Deserialize, Serialize};
+use time::UtcOffset;
 
 #[derive(Deserialize)]
 struct LoginInfo {
@@ -24,11 +43,102 @@ struct LoginInfo {
     password: String,
 }
 
+const USERNAME: Rule = Rule {
+    field: "username",
+    label: "the username",
+    trim: true,
+    min_chars: 3,
+    max_chars: 32,
+    charset: Charset::Username,
+};
+
+const PASSWORD: Rule = Rule {
+    field: "password",
+    label: "the password",
+    trim: false,
+    min_chars: 8,
+    max_chars: 128,
+    charset: Charset::Line,
+};
+
+// Signing in only needs the fields to be there; accounts may predate the
+// current registration rules.
+const LOGIN_USERNAME: Rule = Rule {
+    min_chars: 1,
+    max_chars: 128,
+    charset: Charset::Line,
+    ..USERNAME
+};
+
+const LOGIN_PASSWORD: Rule = Rule {
+    min_chars: 1,
+    ..PASSWORD
+};
+
+impl LoginInfo {
+    fn checked(mut self, username: &Rule, password: &Rule) -> Result<LoginInfo, InvalidInput> {
+        let errors = validate::check(&mut [
+            (username, &mut self.username),
+            (password, &mut self.password),
+        ]);
+        if errors.is_empty() {
+            Ok(self)
+        } else {
+            Err(InvalidInput(errors))
+        }
+    }
+}
+
 #[derive(Serialize)]
 struct ResponseTask {
     task_id: i64,
+    list_id: i64,
+    parent_id: Option<i64>,
+    position: i64,
     task_title: String,
     task_description: String,
+    done: bool,
+    completed_at: Option<String>,
+    due_at: Option<String>,
+    start_at: Option<String>,
+    priority: i64,
+    tags: Vec<String>,
+    recurrence: Option<String>,
+}
+
+impl From<Task> for ResponseTask {
+    fn from(task: Task) -> Self {
+        ResponseTask {
+            task_id: task.task_id,
+            list_id: task.list_id,
+            parent_id: task.parent_id,
+            position: task.position,
+            task_title: task.title,
+            task_description: task.description,
+            done: task.done,
+            completed_at: task.completed_at,
+            due_at: task.due_at,
+            start_at: task.start_at,
+            priority: task.priority,
+            tags: task.tags,
+            recurrence: task.recurrence,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ResponseTag {
+    tag_id: i64,
+    name: String,
+}
+
+impl From<Tag> for ResponseTag {
+    fn from(tag: Tag) -> Self {
+        ResponseTag {
+            tag_id: tag.tag_id,
+            name: tag.name,
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -36,68 +146,447 @@ struct Response {
     user_id: i64,
     username: String,
     tasks: Vec<ResponseTask>,
+    groups: TaskGroups,
+    // see `TaskChanges`
+    revision: i64,
     success: bool,
     message: String,
 }
 
-#[derive(Serialize, Deserialize)]
-struct SessionInfo {
-    user_id: i64,
-    username: String,
+// The lean answer to a task mutation: only the tasks the change touched, the ids
+// of those that are gone or no longer match the list filter, and the revision
+// after the change. A client whose copy is older than `revision - 1` missed a
+// change and has to reload the list.
+#[derive(Serialize)]
+struct TaskChanges {
+    revision: i64,
+    tasks: Vec<ResponseTask>,
+    removed: Vec<i64>,
+    groups: TaskGroups,
+    success: bool,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct ResponseList {
+    list_id: i64,
+    name: String,
+    open_tasks: i64,
+}
+
+impl From<List> for ResponseList {
+    fn from(list: List) -> Self {
+        ResponseList {
+            list_id: list.list_id,
+            name: list.name,
+            open_tasks: list.open_tasks,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ListResponse {
+    lists: Vec<ResponseList>,
+    success: bool,
+    message: String,
+}
+
+// `title` and `snippet` are HTML with the matched words in `<mark>`.
+#[derive(Serialize)]
+struct ResponseSearchHit {
+    task_id: i64,
+    list_id: i64,
+    parent_id: Option<i64>,
+    done: bool,
+    title: String,
+    snippet: String,
+    rank: f64,
+}
+
+impl From<SearchHit> for ResponseSearchHit {
+    fn from(hit: SearchHit) -> Self {
+        ResponseSearchHit {
+            task_id: hit.task_id,
+            list_id: hit.list_id,
+            parent_id: hit.parent_id,
+            done: hit.done,
+            title: hit.title,
+            snippet: hit.snippet,
+            rank: hit.rank,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SearchResponse {
+    results: Vec<ResponseSearchHit>,
+    success: bool,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    #[serde(default)]
+    q: String,
+}
+
+const SEARCH_LIMIT: i64 = 50;
+
+#[derive(Serialize)]
+struct TagResponse {
+    tags: Vec<ResponseTag>,
+    success: bool,
+    message: String,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum ResponseView {
+    // the whole task list
+    #[default]
+    Full,
+    // only what a mutation changed, see `TaskChanges`
+    Changes,
+}
+
+// Query parameters shaping the task list returned by `/data`, `/login` and the
+// task mutations.
+#[derive(Deserialize)]
+struct ListQuery {
+    // the client's UTC offset in minutes east of UTC, used to decide which
+    // tasks are due "today"
+    #[serde(default)]
+    tz_offset: i32,
+    #[serde(default)]
+    sort: SortKey,
+    order: Option<SortOrder>,
+    // only the tasks of this list; all of the user's tasks when missing
+    list_id: Option<i64>,
+    // comma separated tag names; only tasks carrying all of them are listed
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    view: ResponseView,
+}
+
+impl ListQuery {
+    fn offset(&self) -> UtcOffset {
+        UtcOffset::from_whole_seconds(self.tz_offset.saturating_mul(60)).unwrap_or(UtcOffset::UTC)
+    }
+
+    fn sort(&self) -> TaskSort {
+        TaskSort {
+            sort: self.sort,
+            order: self.order,
+        }
+    }
+
+    fn filter(&self) -> TaskFilter {
+        TaskFilter {
+            list_id: self.list_id,
+            tags: self
+                .tags
+                .split(',')
+                .filter_map(|name| normalize_tag(name).ok())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ListInfo {
+    #[serde(default)]
+    name: String,
+}
+
+const LIST_NAME: Rule = Rule {
+    field: "name",
+    label: "a list name",
+    trim: true,
+    min_chars: 1,
+    max_chars: 64,
+    charset: Charset::Line,
+};
+
+fn normalize_list_name(name: &str) -> Result<String, InvalidInput> {
+    let mut name = name.to_string();
+    match LIST_NAME.check(&mut name) {
+        Some(error) => Err(InvalidInput(vec![error])),
+        None => Ok(name),
+    }
+}
+
+#[derive(Deserialize)]
+struct TagInfo {
+    #[serde(default)]
+    name: String,
+}
+
+// Tags are matched without case and may be written with a leading '#'.
+fn normalize_tag(name: &str) -> Result<String, String> {
+    let name = name.trim().trim_start_matches('#').trim();
+    if name.is_empty() {
+        return Err("a tag name must not be empty".to_string());
+    }
+    if name.chars().count() > 32 {
+        return Err(format!("the tag '{name}' is longer than 32 characters"));
+    }
+    if name.contains(',') || name.chars().any(char::is_control) {
+        return Err(format!(
+            "the tag '{name}' contains a comma or control character"
+        ));
+    }
+    Ok(name.to_string())
 }
 
 #[derive(Serialize, Deserialize)]
 struct TaskInfo {
+    #[serde(default)]
     task_id: i64,
+    // the list to create the task in, or to move it to
+    #[serde(default)]
+    list_id: Option<i64>,
+    // creates the task as a subtask of this one
+    #[serde(default)]
+    parent_id: Option<i64>,
     task_title: String,
     task_description: String,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    due_at: Option<String>,
+    #[serde(default)]
+    start_at: Option<String>,
+    #[serde(default)]
+    priority: i64,
+    // `None` keeps the current tags on update.
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    // an RRULE such as "FREQ=WEEKLY;BYDAY=MO"; empty or missing for a task that
+    // does not repeat
+    #[serde(default)]
+    recurrence: Option<String>,
 }
 
-#[get("/data")]
-async fn data(user_tasks_db: Data<UserTasksDB>, session: Session) -> impl Responder {
-    match session.get::<SessionInfo>("session_id") {
-        Err(err) => {
-            println!("{err}");
-            Json(Response {
-                user_id: -1,
-                username: "Anon".to_string(),
-                tasks: vec![],
-                success: false,
-                message: "SessionGetError".to_string(),
-            })
-            .customize()
-            .with_status(StatusCode::INTERNAL_SERVER_ERROR)
+const TASK_TITLE: Rule = Rule {
+    field: "task_title",
+    label: "the title",
+    trim: true,
+    min_chars: 1,
+    max_chars: 200,
+    charset: Charset::Line,
+};
+
+const TASK_DESCRIPTION: Rule = Rule {
+    field: "task_description",
+    label: "the description",
+    trim: false,
+    min_chars: 0,
+    max_chars: 10_000,
+    charset: Charset::Text,
+};
+
+impl TaskInfo {
+    // Dates may come with any UTC offset; the database only stores UTC. Every
+    // field is checked so that all problems are reported at once.
+    fn to_input(&self) -> Result<TaskInput, InvalidInput> {
+        let mut title = self.task_title.clone();
+        let mut description = self.task_description.clone();
+        let mut errors = validate::check(&mut [
+            (&TASK_TITLE, &mut title),
+            (&TASK_DESCRIPTION, &mut description),
+        ]);
+        let mut normalize = |field: &str, value: &Option<String>| match value
+            .as_deref()
+            .filter(|value| !value.is_empty())
+        {
+            Some(value) => schedule::normalize(value)
+                .map_err(|message| errors.push(FieldError::new(field, message)))
+                .ok(),
+            None => None,
+        };
+        let due_at = normalize("due_at", &self.due_at);
+        let start_at = normalize("start_at", &self.start_at);
+        if let (Some(start_at), Some(due_at)) = (&start_at, &due_at) {
+            if start_at > due_at {
+                errors.push(FieldError::new(
+                    "start_at",
+                    "the start date must not be after the due date",
+                ));
+            }
         }
-        Ok(result) => match result {
-            Some(session_data) => {
-                let mut tasks: Vec<ResponseTask> = vec![];
-                for task in user_tasks_db.get_tasks_by_user_id(session_data.user_id) {
-                    tasks.push(ResponseTask {
-                        task_id: task.task_id,
-                        task_title: task.title,
-                        task_description: task.description,
-                    })
+        let recurrence = match self
+            .recurrence
+            .as_deref()
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+        {
+            Some(rule) => rule
+                .parse::<Recurrence>()
+                .map(|rule| rule.to_string())
+                .map_err(|message| errors.push(FieldError::new("recurrence", message)))
+                .ok(),
+            None => None,
+        };
+        if !(0..=3).contains(&self.priority) {
+            errors.push(FieldError::new(
+                "priority",
+                "the priority must be between 0 and 3",
+            ));
+        }
+        let mut tags: Vec<String> = vec![];
+        for name in self.tags.iter().flatten() {
+            match normalize_tag(name) {
+                Ok(name) if !tags.iter().any(|tag| tag.eq_ignore_ascii_case(&name)) => {
+                    tags.push(name)
                 }
-
-                Json(Response {
-                    user_id: session_data.user_id,
-                    username: session_data.username.to_string(),
-                    tasks: tasks,
-                    success: true,
-                    message: "User logged in!".to_string(),
-                })
-                .customize()
+                Ok(_) => {}
+                Err(message) => errors.push(FieldError::new("tags", message)),
             }
-            None => Json(Response {
-                user_id: -1,
-                username: "Anon".to_string(),
-                tasks: vec![],
+        }
+        if !errors.is_empty() {
+            return Err(InvalidInput(errors));
+        }
+        Ok(TaskInput {
+            title,
+            description,
+            due_at,
+            start_at,
+            priority: self.priority,
+            tags: self.tags.as_ref().map(|_| tags),
+            recurrence,
+            due_kind: None,
+            start_kind: None,
+        })
+    }
+}
+
+// Bodies of the deprecated task endpoints, which only take the task and the
+// change to make.
+#[derive(Deserialize)]
+struct DoneInfo {
+    task_id: i64,
+    done: bool,
+}
+
+#[derive(Deserialize)]
+struct MoveInfo {
+    task_id: i64,
+    list_id: i64,
+}
+
+#[derive(Deserialize)]
+struct TaskIdInfo {
+    task_id: i64,
+}
+
+#[derive(Deserialize)]
+struct ChildrenInfo {
+    task_id: i64,
+    children: Vec<i64>,
+}
+
+// Where to move a task among its siblings; to the end when both are missing.
+#[derive(Deserialize)]
+struct PositionInfo {
+    task_id: i64,
+    before_id: Option<i64>,
+    after_id: Option<i64>,
+}
+
+// One entry per field that failed validation.
+#[derive(Debug)]
+struct InvalidInput(Vec<FieldError>);
+
+impl InvalidInput {
+    fn field(field: &str, message: impl Into<String>) -> Self {
+        InvalidInput(vec![FieldError::new(field, message)])
+    }
+}
+
+impl std::fmt::Display for InvalidInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let messages: Vec<&str> = self.0.iter().map(|error| error.message.as_str()).collect();
+        write!(f, "{}", messages.join("; "))
+    }
+}
+
+impl ResponseError for InvalidInput {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        error::error_response(
+            self.status_code(),
+            "invalid_input",
+            self.to_string(),
+            self.0.clone(),
+        )
+    }
+}
+
+impl ResponseError for DbError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DbError::TaskNotFound
+            | DbError::TagNotFound
+            | DbError::ListNotFound
+            | DbError::ParentNotFound
+            | DbError::FeedNotFound
+            | DbError::AppPasswordNotFound => StatusCode::NOT_FOUND,
+            DbError::NestedSubtask | DbError::NotRecurring => StatusCode::BAD_REQUEST,
+            DbError::TitleConflict(_)
+            | DbError::UsernameConflict
+            | DbError::TagConflict
+            | DbError::ListConflict => StatusCode::CONFLICT,
+            DbError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            DbError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let fields = match self {
+            DbError::TitleConflict(_) => vec![FieldError::new("task_title", self.to_string())],
+            _ => vec![],
+        };
+        error::error_response(self.status_code(), self.code(), self.to_string(), fields)
+    }
+}
+
+#[get("/data")]
+async fn data(
+    user_tasks_db: Data<UserTasksDB>,
+    user: OptionalUser,
+    list_query: web::Query<ListQuery>,
+) -> Result<impl Responder, DbError> {
+    Ok(match user.0 {
+        Some(user) => {
+            let revision = user_tasks_db.get_revision(user.user_id).await?;
+            let tasks = user_tasks_db
+                .get_tasks_by_user_id(user.user_id, list_query.sort(), list_query.filter())
+                .await?;
+
+            Json(Response {
+                user_id: user.user_id,
+                username: user.username,
+                groups: TaskGroups::new(&tasks, list_query.offset()),
+                tasks: tasks.into_iter().map(ResponseTask::from).collect(),
+                revision,
                 success: true,
-                message: "Not logged in!".to_string(),
+                message: "User logged in!".to_string(),
             })
-            .customize(),
-        },
-    }
+        }
+        None => Json(Response {
+            user_id: -1,
+            username: "Anon".to_string(),
+            tasks: vec![],
+            groups: TaskGroups::default(),
+            revision: 0,
+            success: true,
+            message: "Not logged in!".to_string(),
+        }),
+    })
 }
 
 #[post("/login")]
@@ -105,313 +594,599 @@ async fn login(
     user_tasks_db: Data<UserTasksDB>,
     login_info: web::Json<LoginInfo>,
     session: Session,
-) -> impl Responder {
-    let users = user_tasks_db.get_user_by_credentials(&login_info.username, &login_info.password);
-    let mut response = Response {
+    list_query: web::Query<ListQuery>,
+) -> Result<impl Responder> {
+    let login_info = login_info
+        .into_inner()
+        .checked(&LOGIN_USERNAME, &LOGIN_PASSWORD)?;
+    let users = user_tasks_db
+        .get_user_by_credentials(login_info.username.clone(), login_info.password.clone())
+        .await?;
+    if users.is_empty() {
+        return Err(AuthError::InvalidCredentials.into());
+    }
+    if users.len() > 1 {
+        println!("more than one user named {}", login_info.username);
+        return Err(DbError::Internal.into());
+    }
+    let current_user = CurrentUser {
+        user_id: users[0].user_id,
+        username: users[0].username.clone(),
+    };
+    current_user.log_in(&session)?;
+    let revision = user_tasks_db.get_revision(users[0].user_id).await?;
+    let tasks = user_tasks_db
+        .get_tasks_by_user_id(users[0].user_id, list_query.sort(), list_query.filter())
+        .await?;
+    Ok(Json(Response {
+        user_id: users[0].user_id,
+        username: users[0].username.clone(),
+        groups: TaskGroups::new(&tasks, list_query.offset()),
+        tasks: tasks.into_iter().map(ResponseTask::from).collect(),
+        revision,
+        success: true,
+        message: "Logged in successfully!".to_string(),
+    }))
+}
+
+#[post("/register")]
+async fn register(
+    user_tasks_db: Data<UserTasksDB>,
+    login_info: web::Json<LoginInfo>,
+    session: Session,
+) -> Result<impl Responder> {
+    let login_info = login_info.into_inner().checked(&USERNAME, &PASSWORD)?;
+    user_tasks_db
+        .create_user(login_info.username.clone(), login_info.password.clone())
+        .await?;
+
+    let users = user_tasks_db
+        .get_user_by_username(login_info.username.clone())
+        .await?;
+    let current_user = CurrentUser {
+        user_id: users[0].user_id,
+        username: users[0].username.clone(),
+    };
+    current_user.log_in(&session)?;
+    Ok(Json(Response {
+        user_id: users[0].user_id,
+        username: users[0].username.clone(),
+        tasks: vec![],
+        groups: TaskGroups::default(),
+        revision: 0,
+        success: true,
+        message: "Registered successfully!".to_string(),
+    })
+    .customize()
+    .with_status(StatusCode::CREATED))
+}
+
+#[delete("/logout")]
+async fn logout(user: OptionalUser, session: Session) -> Result<impl Responder, AuthError> {
+    if user.0.is_none() {
+        return Err(AuthError::Unauthorized);
+    }
+    session.clear();
+    Ok(Json(Response {
         user_id: -1,
         username: "Anon".to_string(),
         tasks: vec![],
-        success: false,
-        message: "Login: No User found!".to_string(),
+        groups: TaskGroups::default(),
+        revision: 0,
+        success: true,
+        message: "Logged out successfully".to_string(),
+    }))
+}
+
+// Builds the response for a task mutation: the user's current tasks, or with
+// `view=changes` only the ones in `result`, along with the revision the change
+// was recorded under. A failed change answers with the error body instead.
+async fn task_mutation_response(
+    user_tasks_db: &UserTasksDB,
+    user: CurrentUser,
+    list_query: &ListQuery,
+    action: &str,
+    result: Result<(Vec<i64>, i64), impl ResponseError>,
+) -> Result<HttpResponse, DbError> {
+    let (task_ids, revision) = match result {
+        Ok(changed) => changed,
+        Err(err) => return Ok(err.error_response()),
     };
-    let mut status_code = StatusCode::BAD_REQUEST;
-    if users.len() == 0 {
-    } else if users.len() == 1 {
-        let session_info = SessionInfo {
-            user_id: users[0].user_id,
-            username: users[0].username.clone(),
-        };
-        let _ = session.insert::<SessionInfo>("session_id", session_info);
-        response.user_id = users[0].user_id;
-        response.username = users[0].username.clone();
-        let mut tasks: Vec<ResponseTask> = vec![];
-        for task in user_tasks_db.get_tasks_by_user_id(users[0].user_id) {
-            tasks.push(ResponseTask {
-                task_id: task.task_id,
-                task_title: task.title,
-                task_description: task.description,
+    let message = format!("{action}: successful!");
+    Ok(match list_query.view {
+        ResponseView::Full => {
+            let tasks = user_tasks_db
+                .get_tasks_by_user_id(user.user_id, list_query.sort(), list_query.filter())
+                .await?;
+            HttpResponse::Ok().json(Response {
+                user_id: user.user_id,
+                username: user.username,
+                groups: TaskGroups::new(&tasks, list_query.offset()),
+                tasks: tasks.into_iter().map(ResponseTask::from).collect(),
+                revision,
+                success: true,
+                message,
             })
         }
-        response.tasks = tasks;
-        response.success = true;
-        response.message = "Logged in successfully!".to_string();
-        status_code = StatusCode::OK;
-    } else {
-        response.message = "DB fail more than 1 user".to_string();
-        status_code = StatusCode::INTERNAL_SERVER_ERROR;
-    }
-    Json(response).customize().with_status(status_code)
+        ResponseView::Changes => HttpResponse::Ok().json(
+            task_changes(
+                user_tasks_db,
+                user.user_id,
+                list_query,
+                task_ids,
+                revision,
+                message,
+            )
+            .await?,
+        ),
+    })
 }
 
-#[delete("/logout")]
-async fn logout(session: Session) -> impl Responder {
-    match session.get::<SessionInfo>("session_id") {
-        Err(err) => {
-            println!("{err}");
-            Json(Response {
-                user_id: -1,
-                username: "Anon".to_string(),
-                tasks: vec![],
-                success: false,
-                message: "Logout : SessionGetError".to_string(),
-            })
-            .customize()
-            .with_status(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-        Ok(result) => match result {
-            Some(session_data) => {
-                session.clear();
-                Json(Response {
-                    user_id: -1,
-                    username: "Anon".to_string(),
-                    tasks: vec![],
-                    success: true,
-                    message: format!("Logged out successfully"),
-                })
-                .customize()
-            }
-            None => Json(Response {
-                user_id: -1,
-                username: "Anon".to_string(),
-                tasks: vec![],
-                success: false,
-                message: "Already logged out!".to_string(),
-            })
-            .customize(),
-        },
-    }
+async fn task_changes(
+    user_tasks_db: &UserTasksDB,
+    user_id: i64,
+    list_query: &ListQuery,
+    task_ids: Vec<i64>,
+    revision: i64,
+    message: String,
+) -> Result<TaskChanges, DbError> {
+    let filter = list_query.filter();
+    let tasks: Vec<Task> = user_tasks_db
+        .get_tasks_by_ids(user_id, task_ids.clone())
+        .await?
+        .into_iter()
+        .filter(|task| filter.matches(task))
+        .collect();
+    let removed = task_ids
+        .into_iter()
+        .filter(|task_id| !tasks.iter().any(|task| task.task_id == *task_id))
+        .collect();
+    Ok(TaskChanges {
+        revision,
+        groups: TaskGroups::new(&tasks, list_query.offset()),
+        tasks: tasks.into_iter().map(ResponseTask::from).collect(),
+        removed,
+        success: true,
+        message,
+    })
+}
+
+// The task routes taking the task id in the body are superseded by `/tasks/{id}`
+// and only kept until clients have moved over.
+fn deprecated(mut response: HttpResponse) -> HttpResponse {
+    let headers = response.headers_mut();
+    headers.insert(
+        header::HeaderName::from_static("deprecation"),
+        header::HeaderValue::from_static("true"),
+    );
+    headers.insert(
+        header::LINK,
+        header::HeaderValue::from_static("</tasks>; rel=\"successor-version\""),
+    );
+    response
 }
 
 #[post("/task")]
 async fn task_create(
     user_tasks_db: Data<UserTasksDB>,
     task_info: web::Json<TaskInfo>,
-    session: Session,
-) -> impl Responder {
-    match session.get::<SessionInfo>("session_id") {
+    user: CurrentUser,
+    list_query: web::Query<ListQuery>,
+) -> Result<impl Responder, DbError> {
+    let task = match task_info.to_input() {
+        Ok(task) => task,
         Err(err) => {
-            println!("{err}");
-            Json(Response {
-                user_id: -1,
-                username: "Anon".to_string(),
-                tasks: vec![],
-                success: false,
-                message: "Create task : SessionGetError".to_string(),
-            })
-            .customize()
-            .with_status(StatusCode::INTERNAL_SERVER_ERROR)
+            return task_mutation_response(
+                &user_tasks_db,
+                user,
+                &list_query,
+                "Create task",
+                Err(err),
+            )
+            .await
+            .map(deprecated)
         }
-        Ok(result) => match result {
-            Some(session_data) => {
-                let success = user_tasks_db.create_task(
-                    session_data.user_id,
-                    task_info.task_title.clone(),
-                    task_info.task_description.clone(),
-                );
-
-                let mut tasks: Vec<ResponseTask> = vec![];
-                for task in user_tasks_db.get_tasks_by_user_id(session_data.user_id) {
-                    tasks.push(ResponseTask {
-                        task_id: task.task_id,
-                        task_title: task.title,
-                        task_description: task.description,
-                    })
-                }
-                Json(Response {
-                    user_id: session_data.user_id,
-                    username: session_data.username.to_string(),
-                    tasks: tasks,
-                    success: success,
-                    message: format!(
-                        "Create task: {}!",
-                        if success { "successful" } else { "failed" }
-                    ),
-                })
-                .customize()
-                .with_status(if success {
-                    StatusCode::OK
-                } else {
-                    StatusCode::BAD_REQUEST
-                })
-            }
-            None => Json(Response {
-                user_id: -1,
-                username: "Anon".to_string(),
-                tasks: vec![],
-                success: false,
-                message: "Create task: unauthorized!".to_string(),
-            })
-            .customize()
-            .with_status(StatusCode::UNAUTHORIZED),
-        },
-    }
+    };
+    let result = match user_tasks_db
+        .create_task(
+            user.user_id,
+            task_info.list_id,
+            task_info.parent_id,
+            task,
+            task_info.done,
+        )
+        .await
+    {
+        Ok((task_id, revision)) => user_tasks_db
+            .task_family(task_id, user.user_id)
+            .await
+            .map(|task_ids| (task_ids, revision)),
+        Err(err) => Err(err),
+    };
+    task_mutation_response(&user_tasks_db, user, &list_query, "Create task", result)
+        .await
+        .map(deprecated)
 }
 
 #[put("/task")]
 async fn task_update(
     user_tasks_db: Data<UserTasksDB>,
     task_info: web::Json<TaskInfo>,
-    session: Session,
-) -> impl Responder {
-    match session.get::<SessionInfo>("session_id") {
+    user: CurrentUser,
+    list_query: web::Query<ListQuery>,
+) -> Result<impl Responder, DbError> {
+    let task = match task_info.to_input() {
+        Ok(task) => task,
         Err(err) => {
-            println!("{err}");
-            Json(Response {
-                user_id: -1,
-                username: "Anon".to_string(),
-                tasks: vec![],
-                success: false,
-                message: "Update task : SessionGetError".to_string(),
-            })
-            .customize()
-            .with_status(StatusCode::INTERNAL_SERVER_ERROR)
+            return task_mutation_response(
+                &user_tasks_db,
+                user,
+                &list_query,
+                "Update task",
+                Err(err),
+            )
+            .await
+            .map(deprecated)
         }
-        Ok(result) => match result {
-            Some(session_data) => {
-                let success = user_tasks_db.update_task(
-                    task_info.task_id,
-                    session_data.user_id,
-                    task_info.task_title.clone(),
-                    task_info.task_description.clone(),
-                );
-
-                let mut tasks: Vec<ResponseTask> = vec![];
-                for task in user_tasks_db.get_tasks_by_user_id(session_data.user_id) {
-                    tasks.push(ResponseTask {
-                        task_id: task.task_id,
-                        task_title: task.title,
-                        task_description: task.description,
-                    })
-                }
-                Json(Response {
-                    user_id: session_data.user_id,
-                    username: session_data.username.to_string(),
-                    tasks: tasks,
-                    success: success,
-                    message: format!(
-                        "Update task: {}!",
-                        if success { "successful" } else { "failed" }
-                    ),
-                })
-                .customize()
-                .with_status(if success {
-                    StatusCode::OK
-                } else {
-                    StatusCode::BAD_REQUEST
-                })
-            }
-            None => Json(Response {
-                user_id: -1,
-                username: "Anon".to_string(),
-                tasks: vec![],
-                success: false,
-                message: "Update task: unauthorized!".to_string(),
-            })
-            .customize()
-            .with_status(StatusCode::UNAUTHORIZED),
-        },
-    }
+    };
+    let result = user_tasks_db
+        .update_task(task_info.task_id, user.user_id, task)
+        .await
+        .map(|revision| (vec![task_info.task_id], revision));
+    task_mutation_response(&user_tasks_db, user, &list_query, "Update task", result)
+        .await
+        .map(deprecated)
+}
+
+#[put("/task/done")]
+async fn task_done(
+    user_tasks_db: Data<UserTasksDB>,
+    done_info: web::Json<DoneInfo>,
+    user: CurrentUser,
+    list_query: web::Query<ListQuery>,
+) -> Result<impl Responder, DbError> {
+    let affected = user_tasks_db
+        .task_family(done_info.task_id, user.user_id)
+        .await?;
+    let result = user_tasks_db
+        .set_task_done(
+            done_info.task_id,
+            user.user_id,
+            done_info.done,
+            list_query.offset(),
+        )
+        .await
+        .map(|revision| (affected, revision));
+    task_mutation_response(&user_tasks_db, user, &list_query, "Complete task", result).await
+}
+
+#[put("/task/list")]
+async fn task_move(
+    user_tasks_db: Data<UserTasksDB>,
+    move_info: web::Json<MoveInfo>,
+    user: CurrentUser,
+    list_query: web::Query<ListQuery>,
+) -> Result<impl Responder, DbError> {
+    let affected = user_tasks_db
+        .task_family(move_info.task_id, user.user_id)
+        .await?;
+    let result = user_tasks_db
+        .move_task(move_info.task_id, user.user_id, move_info.list_id)
+        .await
+        .map(|revision| (affected, revision));
+    task_mutation_response(&user_tasks_db, user, &list_query, "Move task", result).await
+}
+
+#[put("/task/children")]
+async fn task_reorder_children(
+    user_tasks_db: Data<UserTasksDB>,
+    children_info: web::Json<ChildrenInfo>,
+    user: CurrentUser,
+    list_query: web::Query<ListQuery>,
+) -> Result<impl Responder, DbError> {
+    let children_info = children_info.into_inner();
+    let affected = user_tasks_db
+        .task_family(children_info.task_id, user.user_id)
+        .await?;
+    let result = user_tasks_db
+        .reorder_children(children_info.task_id, user.user_id, children_info.children)
+        .await
+        .map(|revision| (affected, revision));
+    task_mutation_response(
+        &user_tasks_db,
+        user,
+        &list_query,
+        "Reorder subtasks",
+        result,
+    )
+    .await
+}
+
+#[put("/task/position")]
+async fn task_place(
+    user_tasks_db: Data<UserTasksDB>,
+    position_info: web::Json<PositionInfo>,
+    user: CurrentUser,
+    list_query: web::Query<ListQuery>,
+) -> Result<impl Responder, DbError> {
+    let result = user_tasks_db
+        .place_task(
+            position_info.task_id,
+            user.user_id,
+            position_info.before_id,
+            position_info.after_id,
+        )
+        .await;
+    task_mutation_response(&user_tasks_db, user, &list_query, "Move task", result).await
 }
 
 #[delete("/task")]
 async fn task_delete(
     user_tasks_db: Data<UserTasksDB>,
-    task_info: web::Json<TaskInfo>,
-    session: Session,
-) -> impl Responder {
-    match session.get::<SessionInfo>("session_id") {
-        Err(err) => {
-            println!("{err}");
-            Json(Response {
-                user_id: -1,
-                username: "Anon".to_string(),
-                tasks: vec![],
-                success: false,
-                message: "Delete task : SessionGetError".to_string(),
-            })
-            .customize()
-            .with_status(StatusCode::INTERNAL_SERVER_ERROR)
+    task_id_info: web::Json<TaskIdInfo>,
+    user: CurrentUser,
+    list_query: web::Query<ListQuery>,
+) -> Result<impl Responder, DbError> {
+    let affected = user_tasks_db
+        .task_family(task_id_info.task_id, user.user_id)
+        .await?;
+    let result = user_tasks_db
+        .delete_task(task_id_info.task_id, user.user_id)
+        .await
+        .map(|revision| (affected, revision));
+    task_mutation_response(&user_tasks_db, user, &list_query, "Delete task", result)
+        .await
+        .map(deprecated)
+}
+
+#[get("/tasks/search")]
+async fn task_search(
+    user_tasks_db: Data<UserTasksDB>,
+    search_query: web::Query<SearchQuery>,
+    user: CurrentUser,
+) -> Result<impl Responder, DbError> {
+    let results = user_tasks_db
+        .search_tasks(user.user_id, search_query.q.clone(), SEARCH_LIMIT)
+        .await?;
+    Ok(Json(SearchResponse {
+        message: format!("Search: {} tasks found!", results.len()),
+        results: results.into_iter().map(ResponseSearchHit::from).collect(),
+        success: true,
+    }))
+}
+
+async fn tag_response(
+    user_tasks_db: &UserTasksDB,
+    user: &CurrentUser,
+    action: &str,
+    result: Result<(), impl ResponseError>,
+) -> Result<HttpResponse, DbError> {
+    if let Err(err) = result {
+        return Ok(err.error_response());
+    }
+    let tags = user_tasks_db.get_tags_by_user_id(user.user_id).await?;
+    Ok(HttpResponse::Ok().json(TagResponse {
+        tags: tags.into_iter().map(ResponseTag::from).collect(),
+        success: true,
+        message: format!("{action}: successful!"),
+    }))
+}
+
+#[get("/tags")]
+async fn tags_list(
+    user_tasks_db: Data<UserTasksDB>,
+    user: CurrentUser,
+) -> Result<impl Responder, DbError> {
+    tag_response(&user_tasks_db, &user, "List tags", Ok::<_, DbError>(())).await
+}
+
+#[post("/tags")]
+async fn tag_create(
+    user_tasks_db: Data<UserTasksDB>,
+    tag_info: web::Json<TagInfo>,
+    user: CurrentUser,
+) -> Result<impl Responder, DbError> {
+    let name = match normalize_tag(&tag_info.name) {
+        Ok(name) => name,
+        Err(message) => {
+            let err = InvalidInput::field("name", message);
+            return tag_response(&user_tasks_db, &user, "Create tag", Err(err)).await;
         }
-        Ok(result) => match result {
-            Some(session_data) => {
-                let success = user_tasks_db.delete_task(task_info.task_id, session_data.user_id);
-
-                let mut tasks: Vec<ResponseTask> = vec![];
-                for task in user_tasks_db.get_tasks_by_user_id(session_data.user_id) {
-                    tasks.push(ResponseTask {
-                        task_id: task.task_id,
-                        task_title: task.title,
-                        task_description: task.description,
-                    })
-                }
-                Json(Response {
-                    user_id: session_data.user_id,
-                    username: session_data.username.to_string(),
-                    tasks: tasks,
-                    success: success,
-                    message: format!(
-                        "Delete task: {}!",
-                        if success { "successful" } else { "failed" }
-                    ),
-                })
-                .customize()
-                .with_status(if success {
-                    StatusCode::OK
-                } else {
-                    StatusCode::BAD_REQUEST
-                })
-            }
-            None => Json(Response {
-                user_id: -1,
-                username: "Anon".to_string(),
-                tasks: vec![],
-                success: false,
-                message: "Delete task: unauthorized!".to_string(),
-            })
-            .customize()
-            .with_status(StatusCode::UNAUTHORIZED),
-        },
+    };
+    let result = user_tasks_db.create_tag(user.user_id, name).await;
+    tag_response(&user_tasks_db, &user, "Create tag", result).await
+}
+
+#[put("/tags/{tag_id}")]
+async fn tag_rename(
+    user_tasks_db: Data<UserTasksDB>,
+    tag_id: web::Path<i64>,
+    tag_info: web::Json<TagInfo>,
+    user: CurrentUser,
+) -> Result<impl Responder, DbError> {
+    let name = match normalize_tag(&tag_info.name) {
+        Ok(name) => name,
+        Err(message) => {
+            let err = InvalidInput::field("name", message);
+            return tag_response(&user_tasks_db, &user, "Rename tag", Err(err)).await;
+        }
+    };
+    let result = user_tasks_db.rename_tag(*tag_id, user.user_id, name).await;
+    tag_response(&user_tasks_db, &user, "Rename tag", result.map(|_| ())).await
+}
+
+#[delete("/tags/{tag_id}")]
+async fn tag_delete(
+    user_tasks_db: Data<UserTasksDB>,
+    tag_id: web::Path<i64>,
+    user: CurrentUser,
+) -> Result<impl Responder, DbError> {
+    let result = user_tasks_db.delete_tag(*tag_id, user.user_id).await;
+    tag_response(&user_tasks_db, &user, "Delete tag", result.map(|_| ())).await
+}
+
+async fn list_response(
+    user_tasks_db: &UserTasksDB,
+    user: &CurrentUser,
+    action: &str,
+    result: Result<(), impl ResponseError>,
+) -> Result<HttpResponse, DbError> {
+    if let Err(err) = result {
+        return Ok(err.error_response());
     }
+    let lists = user_tasks_db.get_lists_by_user_id(user.user_id).await?;
+    Ok(HttpResponse::Ok().json(ListResponse {
+        lists: lists.into_iter().map(ResponseList::from).collect(),
+        success: true,
+        message: format!("{action}: successful!"),
+    }))
 }
 
-async fn index(req: HttpRequest) -> actix_web::Result<NamedFile> {
-    let path: PathBuf = req
-        .match_info()
-        .query("../dist/index.html")
-        .parse()
-        .unwrap();
-    Ok(NamedFile::open(path)?)
+#[get("/lists")]
+async fn lists_list(
+    user_tasks_db: Data<UserTasksDB>,
+    user: CurrentUser,
+) -> Result<impl Responder, DbError> {
+    list_response(&user_tasks_db, &user, "List lists", Ok::<_, DbError>(())).await
+}
+
+#[post("/lists")]
+async fn list_create(
+    user_tasks_db: Data<UserTasksDB>,
+    list_info: web::Json<ListInfo>,
+    user: CurrentUser,
+) -> Result<impl Responder, DbError> {
+    let name = match normalize_list_name(&list_info.name) {
+        Ok(name) => name,
+        Err(err) => return list_response(&user_tasks_db, &user, "Create list", Err(err)).await,
+    };
+    let result = user_tasks_db.create_list(user.user_id, name).await;
+    list_response(&user_tasks_db, &user, "Create list", result).await
+}
+
+#[put("/lists/{list_id}")]
+async fn list_rename(
+    user_tasks_db: Data<UserTasksDB>,
+    list_id: web::Path<i64>,
+    list_info: web::Json<ListInfo>,
+    user: CurrentUser,
+) -> Result<impl Responder, DbError> {
+    let name = match normalize_list_name(&list_info.name) {
+        Ok(name) => name,
+        Err(err) => return list_response(&user_tasks_db, &user, "Rename list", Err(err)).await,
+    };
+    let result = user_tasks_db
+        .rename_list(*list_id, user.user_id, name)
+        .await;
+    list_response(&user_tasks_db, &user, "Rename list", result).await
+}
+
+#[delete("/lists/{list_id}")]
+async fn list_delete(
+    user_tasks_db: Data<UserTasksDB>,
+    list_id: web::Path<i64>,
+    user: CurrentUser,
+) -> Result<impl Responder, DbError> {
+    let result = user_tasks_db.delete_list(*list_id, user.user_id).await;
+    list_response(&user_tasks_db, &user, "Delete list", result.map(|_| ())).await
 }
 
 #[actix_web::main]
 async fn main() -> Result<(), std::io::Error> {
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("Invalid configuration: {error}");
+            }
+            std::process::exit(1);
+        }
+    };
+
     // init db
-    let mut user_tasks_db: UserTasksDB = UserTasksDB::new();
-    user_tasks_db.reset();
+    let user_tasks_db: UserTasksDB =
+        UserTasksDB::new(&settings.database_path, settings.database_pool_size)
+            .map_err(std::io::Error::other)?;
+    user_tasks_db.migrate().map_err(std::io::Error::other)?;
+    if std::env::args().any(|arg| arg == "--seed") {
+        user_tasks_db.seed().map_err(std::io::Error::other)?;
+    }
 
-    let secret_key = Key::from(conf::SECRET_KEY);
+    let user_tasks_db = Data::new(user_tasks_db);
+    let secret_key = settings.session_key();
+    let accepted_credentials = Data::new(dav::AcceptedCredentials::new(&secret_key));
+    let cors_origins = settings.cors_origins.clone();
+    let static_dir = settings.static_dir.clone();
 
     // start server
     HttpServer::new(
         move || {
-            let cors = Cors::permissive();
+            let cors = if cors_origins.is_empty() {
+                Cors::permissive()
+            } else {
+                cors_origins
+                    .iter()
+                    .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+                    .allow_any_method()
+                    .allow_any_header()
+                    .supports_credentials()
+            };
             App::new()
                 .wrap(cors)
+                .wrap_fn(error::with_request_id)
                 .wrap(SessionMiddleware::new(
                     CookieSessionStore::default(),
                     secret_key.clone(),
                 ))
-                .app_data(Data::new(UserTasksDB::new()))
+                .app_data(user_tasks_db.clone())
+                .app_data(accepted_credentials.clone())
+                .app_data(error::json_config())
+                .app_data(error::query_config())
+                .app_data(error::path_config())
                 .service(data)
                 .service(login)
+                .service(register)
                 .service(logout)
                 .service(task_create)
                 .service(task_update)
+                .service(task_done)
+                .service(task_move)
+                .service(task_reorder_children)
+                .service(task_place)
+                .service(task_search)
+                .service(tasks::task_get)
+                .service(tasks::task_post)
+                .service(tasks::task_patch)
+                .service(tasks::task_delete)
+                .service(tasks::task_skip)
                 .service(task_delete)
-                .service(actix_files::Files::new("/", ".").index_file("index.html"))
+                .service(tags_list)
+                .service(tag_create)
+                .service(tag_rename)
+                .service(tag_delete)
+                .service(feed::feed_get)
+                .service(feed::feed_reset)
+                .service(feed::feed_disable)
+                .service(feed::feed_calendar)
+                .service(transfer::export)
+                .service(
+                    web::resource("/import")
+                        .app_data(web::PayloadConfig::new(transfer::IMPORT_LIMIT))
+                        .route(web::post().to(transfer::import)),
+                )
+                .service(dav::app_passwords_list)
+                .service(dav::app_password_create)
+                .service(dav::app_password_delete)
+                .service(web::resource(["/dav", "/dav/{path:.*}"]).to(dav::dav))
+                .service(web::resource("/.well-known/caldav").to(dav::well_known))
+                .service(lists_list)
+                .service(list_create)
+                .service(list_rename)
+                .service(list_delete)
+                .service(actix_files::Files::new("/", &static_dir).index_file("index.html"))
         }, // login route
     )
-    .bind(("127.0.0.1", conf::PORT))?
+    .bind((settings.bind_address.as_str(), settings.port))?
     .run()
     .await
 }
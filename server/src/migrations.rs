@@ -0,0 +1,335 @@
+use sqlite::{Connection, State};
+
+// Numbered schema migrations, applied in order. Never edit one that has shipped;
+// append a new entry instead.
+const MIGRATIONS: &[(i64, &str)] = &[
+    (
+        1,
+        "
+    CREATE TABLE IF NOT EXISTS users (
+        user_id INTEGER NOT NULL UNIQUE,
+        username TEXT NOT NULL UNIQUE,
+        password TEXT NOT NULL,
+        PRIMARY KEY('user_id' AUTOINCREMENT)
+    );
+
+    CREATE TABLE IF NOT EXISTS tasks (
+        task_id INTEGER NOT NULL UNIQUE,
+        user_id INTEGER NOT NULL,
+        title TEXT NOT NULL UNIQUE,
+        description TEXT,
+        PRIMARY KEY('task_id' AUTOINCREMENT),
+        FOREIGN KEY('user_id') REFERENCES users('user_id')
+    );
+    ",
+    ),
+    (
+        2,
+        "
+    ALTER TABLE tasks ADD COLUMN done INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE tasks ADD COLUMN completed_at TEXT;
+    ",
+    ),
+    (
+        3,
+        "
+    ALTER TABLE tasks ADD COLUMN due_at TEXT;
+    ALTER TABLE tasks ADD COLUMN start_at TEXT;
+    ",
+    ),
+    (
+        4,
+        "
+    ALTER TABLE tasks ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+    ",
+    ),
+    (
+        5,
+        "
+    CREATE TABLE tags (
+        tag_id INTEGER NOT NULL UNIQUE,
+        user_id INTEGER NOT NULL,
+        name TEXT NOT NULL COLLATE NOCASE,
+        PRIMARY KEY('tag_id' AUTOINCREMENT),
+        FOREIGN KEY('user_id') REFERENCES users('user_id'),
+        UNIQUE('user_id', 'name')
+    );
+
+    CREATE TABLE task_tags (
+        task_id INTEGER NOT NULL,
+        tag_id INTEGER NOT NULL,
+        PRIMARY KEY('task_id', 'tag_id'),
+        FOREIGN KEY('task_id') REFERENCES tasks('task_id'),
+        FOREIGN KEY('tag_id') REFERENCES tags('tag_id')
+    );
+    CREATE INDEX task_tags_tag_id ON task_tags ('tag_id');
+    ",
+    ),
+    (
+        6,
+        "
+    CREATE TABLE lists (
+        list_id INTEGER NOT NULL UNIQUE,
+        user_id INTEGER NOT NULL,
+        name TEXT NOT NULL COLLATE NOCASE,
+        PRIMARY KEY('list_id' AUTOINCREMENT),
+        FOREIGN KEY('user_id') REFERENCES users('user_id'),
+        UNIQUE('user_id', 'name')
+    );
+
+    ALTER TABLE tasks ADD COLUMN list_id INTEGER REFERENCES lists('list_id');
+
+    INSERT INTO lists (user_id, name)
+        SELECT user_id, 'Inbox' FROM users;
+    UPDATE tasks SET list_id = (
+        SELECT list_id FROM lists WHERE lists.user_id = tasks.user_id
+    );
+    CREATE INDEX tasks_list_id ON tasks ('list_id');
+    ",
+    ),
+    (
+        7,
+        "
+    ALTER TABLE tasks ADD COLUMN parent_id INTEGER REFERENCES tasks('task_id');
+    ALTER TABLE tasks ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+    CREATE INDEX tasks_parent_id ON tasks ('parent_id');
+    ",
+    ),
+    (
+        8,
+        "
+    UPDATE tasks SET position = (position + 1) * 1024 WHERE parent_id IS NOT NULL;
+    UPDATE tasks SET position = task_id * 1024 WHERE parent_id IS NULL;
+    ",
+    ),
+    (
+        9,
+        "
+    CREATE VIRTUAL TABLE tasks_search USING fts5(
+        title,
+        description,
+        tokenize = 'unicode61 remove_diacritics 2'
+    );
+    INSERT INTO tasks_search (rowid, title, description)
+        SELECT task_id, title, IFNULL(description, '') FROM tasks;
+    ",
+    ),
+    (
+        10,
+        "
+    ALTER TABLE users ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+    ",
+    ),
+    (
+        11,
+        "
+    CREATE TABLE tasks_new (
+        task_id INTEGER NOT NULL UNIQUE,
+        user_id INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        description TEXT,
+        done INTEGER NOT NULL DEFAULT 0,
+        completed_at TEXT,
+        due_at TEXT,
+        start_at TEXT,
+        priority INTEGER NOT NULL DEFAULT 0,
+        list_id INTEGER REFERENCES lists('list_id'),
+        parent_id INTEGER REFERENCES tasks('task_id'),
+        position INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY('task_id' AUTOINCREMENT),
+        FOREIGN KEY('user_id') REFERENCES users('user_id')
+    );
+    INSERT INTO tasks_new (task_id, user_id, title, description, done, completed_at,
+            due_at, start_at, priority, list_id, parent_id, position)
+        SELECT task_id, user_id, title, description, done, completed_at,
+            due_at, start_at, priority, list_id, parent_id, position
+        FROM tasks;
+    DROP TABLE tasks;
+    ALTER TABLE tasks_new RENAME TO tasks;
+
+    CREATE INDEX tasks_list_id ON tasks ('list_id');
+    CREATE INDEX tasks_parent_id ON tasks ('parent_id');
+    CREATE UNIQUE INDEX tasks_sibling_title ON tasks (list_id, IFNULL(parent_id, 0), title);
+    ",
+    ),
+    (
+        12,
+        "
+    ALTER TABLE tasks ADD COLUMN recurrence TEXT;
+    ",
+    ),
+    (
+        13,
+        "
+    ALTER TABLE users ADD COLUMN feed_token TEXT;
+    CREATE UNIQUE INDEX users_feed_token ON users (feed_token);
+    ",
+    ),
+    (
+        14,
+        "
+    CREATE TABLE app_passwords (
+        app_password_id INTEGER NOT NULL UNIQUE,
+        user_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        password TEXT NOT NULL,
+        prefix TEXT NOT NULL,
+        created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+        PRIMARY KEY('app_password_id' AUTOINCREMENT),
+        FOREIGN KEY('user_id') REFERENCES users('user_id')
+    );
+    CREATE INDEX app_passwords_prefix ON app_passwords ('user_id', 'prefix');
+
+    ALTER TABLE tasks ADD COLUMN dav_uid TEXT;
+    ALTER TABLE tasks ADD COLUMN dav_name TEXT;
+    ALTER TABLE tasks ADD COLUMN due_kind TEXT;
+    ALTER TABLE tasks ADD COLUMN start_kind TEXT;
+    ",
+    ),
+];
+
+pub fn run(connection: &Connection) -> sqlite::Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER NOT NULL UNIQUE,
+            applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );",
+    )?;
+
+    let current_version = current_version(connection)?;
+    for (version, query) in MIGRATIONS {
+        if *version <= current_version {
+            continue;
+        }
+        println!("Applying schema migration {version}");
+        connection.execute("BEGIN;")?;
+        let result = connection.execute(query).and_then(|_| {
+            let mut statement =
+                connection.prepare("INSERT INTO schema_version (version) VALUES (?);")?;
+            statement.bind((1, *version))?;
+            statement.next().map(|_| ())
+        });
+        match result {
+            Ok(_) => connection.execute("COMMIT;")?,
+            Err(err) => {
+                connection.execute("ROLLBACK;")?;
+                return Err(err);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn current_version(connection: &Connection) -> sqlite::Result<i64> {
+    let mut statement =
+        connection.prepare("SELECT IFNULL(MAX(version), 0) AS version FROM schema_version;")?;
+    match statement.next()? {
+        State::Row => statement.read::<i64, _>("version"),
+        State::Done => Ok(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The schema and rows of a database from before migrations were numbered.
+    const BASELINE: &str = "
+    CREATE TABLE users (
+        user_id INTEGER NOT NULL UNIQUE,
+        username TEXT NOT NULL UNIQUE,
+        password TEXT NOT NULL,
+        PRIMARY KEY('user_id' AUTOINCREMENT)
+    );
+    INSERT INTO users VALUES(0, 'user0', 'password0');
+    INSERT INTO users VALUES(NULL, 'user1', 'password1');
+    INSERT INTO users VALUES(NULL, 'user2', 'password2');
+
+    CREATE TABLE tasks (
+        task_id INTEGER NOT NULL UNIQUE,
+        user_id INTEGER NOT NULL,
+        title TEXT NOT NULL UNIQUE,
+        description TEXT,
+        PRIMARY KEY('task_id' AUTOINCREMENT),
+        FOREIGN KEY('user_id') REFERENCES users('user_id')
+    );
+    INSERT INTO tasks VALUES (NULL, 1, 'title 11', 'description 11');
+    INSERT INTO tasks VALUES (NULL, 2, 'title 21', 'description 21');
+    INSERT INTO tasks VALUES (NULL, 2, 'title 31', NULL);
+    ";
+
+    // The first column of every row, as text.
+    fn column(connection: &Connection, query: &str) -> Vec<String> {
+        connection
+            .prepare(query)
+            .unwrap()
+            .into_iter()
+            .map(|row| row.unwrap().read::<&str, _>(0).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn upgrades_a_baseline_database() {
+        let connection = sqlite::open(":memory:").unwrap();
+        connection.execute(BASELINE).unwrap();
+        run(&connection).unwrap();
+
+        let latest = MIGRATIONS.last().unwrap().0;
+        assert_eq!(current_version(&connection).unwrap(), latest);
+
+        // every user gets an Inbox holding their tasks, in their old order
+        assert_eq!(
+            column(
+                &connection,
+                "SELECT user_id || ' ' || name FROM lists ORDER BY user_id;"
+            ),
+            ["0 Inbox", "1 Inbox", "2 Inbox"]
+        );
+        assert_eq!(
+            column(
+                &connection,
+                "SELECT tasks.user_id || ' ' || title || ' ' || IFNULL(description, '-')
+                    || ' ' || done || ' ' || position
+                FROM tasks JOIN lists USING (list_id)
+                WHERE lists.user_id = tasks.user_id AND parent_id IS NULL
+                ORDER BY task_id;"
+            ),
+            [
+                "1 title 11 description 11 0 1024",
+                "2 title 21 description 21 0 2048",
+                "2 title 31 - 0 3072",
+            ]
+        );
+        assert_eq!(
+            column(
+                &connection,
+                "SELECT CAST(rowid AS TEXT) FROM tasks_search WHERE tasks_search MATCH '21';"
+            ),
+            ["2"]
+        );
+
+        // titles are unique among siblings only
+        connection
+            .execute(
+                "INSERT INTO tasks (user_id, list_id, title)
+                SELECT 1, list_id, 'title 21' FROM lists WHERE user_id = 1;",
+            )
+            .unwrap();
+        assert!(connection
+            .execute(
+                "INSERT INTO tasks (user_id, list_id, title)
+                SELECT 1, list_id, 'title 21' FROM lists WHERE user_id = 1;",
+            )
+            .is_err());
+
+        // a second run finds nothing left to do
+        run(&connection).unwrap();
+        assert_eq!(
+            column(
+                &connection,
+                "SELECT CAST(COUNT(*) AS TEXT) FROM schema_version;"
+            ),
+            [MIGRATIONS.len().to_string()]
+        );
+    }
+}
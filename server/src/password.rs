@@ -0,0 +1,42 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+pub enum Verification {
+    Valid,
+    // The stored value predates hashing and should be replaced by a hash.
+    ValidLegacy,
+    Invalid,
+}
+
+pub fn hash(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .unwrap()
+        .to_string()
+}
+
+pub fn verify(password: &str, stored: &str) -> Verification {
+    match PasswordHash::new(stored) {
+        Ok(parsed_hash) => {
+            match Argon2::default().verify_password(password.as_bytes(), &parsed_hash) {
+                Ok(_) => Verification::Valid,
+                Err(_) => Verification::Invalid,
+            }
+        }
+        // Anything that isn't a PHC hash string is a plaintext row from before hashing.
+        Err(_) => {
+            if constant_time_eq(password.as_bytes(), stored.as_bytes()) {
+                Verification::ValidLegacy
+            } else {
+                Verification::Invalid
+            }
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
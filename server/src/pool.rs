@@ -0,0 +1,149 @@
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex},
+};
+
+use sqlite::{ffi, Connection, OpenFlags};
+
+const BUSY_TIMEOUT_MS: usize = 5000;
+
+// A bounded set of SQLite connections. Connections are opened lazily up to `size`
+// and callers block until one is handed back when all of them are in use.
+pub struct Pool {
+    path: PathBuf,
+    size: usize,
+    state: Mutex<PoolState>,
+    available: Condvar,
+}
+
+struct PoolState {
+    idle: Vec<Connection>,
+    open: usize,
+}
+
+pub struct PooledConnection<'a> {
+    pool: &'a Pool,
+    connection: Option<Connection>,
+}
+
+impl Pool {
+    pub fn new(path: &Path, size: usize) -> sqlite::Result<Pool> {
+        // open one connection up front so a bad path fails at startup
+        let connection = open(path)?;
+        Ok(Pool {
+            path: path.to_path_buf(),
+            size,
+            state: Mutex::new(PoolState {
+                idle: vec![connection],
+                open: 1,
+            }),
+            available: Condvar::new(),
+        })
+    }
+
+    pub fn get(&self) -> sqlite::Result<PooledConnection<'_>> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(connection) = state.idle.pop() {
+                return Ok(PooledConnection {
+                    pool: self,
+                    connection: Some(connection),
+                });
+            }
+            if state.open < self.size {
+                state.open += 1;
+                drop(state);
+                return match open(&self.path) {
+                    Ok(connection) => Ok(PooledConnection {
+                        pool: self,
+                        connection: Some(connection),
+                    }),
+                    Err(err) => {
+                        self.state.lock().unwrap().open -= 1;
+                        self.available.notify_one();
+                        Err(err)
+                    }
+                };
+            }
+            state = self.available.wait(state).unwrap();
+        }
+    }
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            // A caller that panicked may have left a transaction open, which
+            // would lock every other connection out of writing. Connections
+            // that cannot be rolled back are closed instead of reused.
+            let usable = !in_transaction(&connection)
+                || (connection.execute("ROLLBACK;").is_ok() && !in_transaction(&connection));
+            let mut state = self.pool.state.lock().unwrap();
+            if usable {
+                state.idle.push(connection);
+            } else {
+                state.open -= 1;
+            }
+            drop(state);
+            self.pool.available.notify_one();
+        }
+    }
+}
+
+fn in_transaction(connection: &Connection) -> bool {
+    // the handle is valid for as long as `connection` is
+    unsafe { ffi::sqlite3_get_autocommit(connection.as_raw()) == 0 }
+}
+
+fn open(path: &Path) -> sqlite::Result<Connection> {
+    // each connection is only ever used by one thread at a time
+    let mut connection = Connection::open_with_flags(
+        path,
+        OpenFlags::new()
+            .with_create()
+            .with_read_write()
+            .with_no_mutex(),
+    )?;
+    connection.set_busy_timeout(BUSY_TIMEOUT_MS)?;
+    connection.execute("PRAGMA journal_mode = WAL;")?;
+    Ok(connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_transaction_is_rolled_back_on_return() {
+        let path = std::env::temp_dir().join(format!("rustodo-pool-{}.sql", std::process::id()));
+        let pool = Pool::new(&path, 1).unwrap();
+        {
+            let connection = pool.get().unwrap();
+            connection
+                .execute("CREATE TABLE IF NOT EXISTS t (x);")
+                .unwrap();
+            connection.execute("BEGIN IMMEDIATE;").unwrap();
+            connection.execute("INSERT INTO t VALUES (1);").unwrap();
+        }
+        let connection = pool.get().unwrap();
+        assert!(!in_transaction(&connection));
+        connection.execute("BEGIN IMMEDIATE; COMMIT;").unwrap();
+        let mut statement = connection.prepare("SELECT COUNT(*) FROM t;").unwrap();
+        statement.next().unwrap();
+        assert_eq!(statement.read::<i64, _>(0).unwrap(), 0);
+        drop(statement);
+        drop(connection);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+}
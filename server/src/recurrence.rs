@@ -0,0 +1,426 @@
+use std::{fmt, str::FromStr};
+
+use time::{
+    macros::format_description, Date, Duration, Month, OffsetDateTime, PrimitiveDateTime,
+    UtcOffset, Weekday,
+};
+
+use crate::db::Task;
+use crate::schedule;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+// How a task repeats: the subset of an RFC 5545 RRULE made of FREQ, INTERVAL,
+// BYDAY (weekly rules), BYMONTHDAY (monthly rules), COUNT and UNTIL.
+#[derive(Clone)]
+pub struct Recurrence {
+    frequency: Frequency,
+    interval: u32,
+    by_day: Vec<Weekday>,
+    // 1 to 31, or -1 for the last day of the month
+    by_month_day: Option<i8>,
+    // occurrences left, the current one included
+    count: Option<u32>,
+    until: Option<OffsetDateTime>,
+}
+
+const WEEKDAYS: [(&str, Weekday); 7] = [
+    ("MO", Weekday::Monday),
+    ("TU", Weekday::Tuesday),
+    ("WE", Weekday::Wednesday),
+    ("TH", Weekday::Thursday),
+    ("FR", Weekday::Friday),
+    ("SA", Weekday::Saturday),
+    ("SU", Weekday::Sunday),
+];
+
+fn number<T: FromStr + PartialOrd>(name: &str, value: &str, min: T, max: T) -> Result<T, String> {
+    value
+        .parse::<T>()
+        .ok()
+        .filter(|number| *number >= min && *number <= max)
+        .ok_or_else(|| format!("{name} '{value}' is out of range"))
+}
+
+fn weekday(value: &str) -> Result<Weekday, String> {
+    WEEKDAYS
+        .iter()
+        .find(|(code, _)| code.eq_ignore_ascii_case(value))
+        .map(|(_, weekday)| *weekday)
+        .ok_or_else(|| format!("'{value}' is not a weekday (MO to SU)"))
+}
+
+// UNTIL is either a date, meaning the end of that day, or a UTC date-time.
+fn until(value: &str) -> Result<OffsetDateTime, String> {
+    let date_time = format_description!("[year][month][day]T[hour][minute][second]Z");
+    let date = format_description!("[year][month][day]");
+    PrimitiveDateTime::parse(value, date_time)
+        .or_else(|_| Date::parse(value, date).map(|date| date.with_hms(23, 59, 59).unwrap()))
+        .map(PrimitiveDateTime::assume_utc)
+        .map_err(|_| format!("UNTIL '{value}' is not a date like 20261231 or 20261231T120000Z"))
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    // Accepts the rule with or without the "RRULE:" prefix, in any case.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let value = match value.get(..6) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("RRULE:") => &value[6..],
+            _ => value,
+        };
+        let mut frequency = None;
+        let mut recurrence = Recurrence {
+            frequency: Frequency::Daily,
+            interval: 1,
+            by_day: vec![],
+            by_month_day: None,
+            count: None,
+            until: None,
+        };
+        for part in value.split(';').filter(|part| !part.is_empty()) {
+            let Some((name, value)) = part.split_once('=') else {
+                return Err(format!("'{part}' is not of the form NAME=VALUE"));
+            };
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(format!("FREQ '{value}' is not supported")),
+                    })
+                }
+                "INTERVAL" => recurrence.interval = number("INTERVAL", value, 1, 999)?,
+                "BYDAY" => {
+                    recurrence.by_day = value.split(',').map(weekday).collect::<Result<_, _>>()?;
+                    recurrence
+                        .by_day
+                        .sort_by_key(|day| day.number_days_from_monday());
+                    recurrence.by_day.dedup();
+                }
+                "BYMONTHDAY" => {
+                    let day = number("BYMONTHDAY", value, -1, 31)?;
+                    if day == 0 {
+                        return Err("BYMONTHDAY must be 1 to 31, or -1 for the last day".into());
+                    }
+                    recurrence.by_month_day = Some(day);
+                }
+                "COUNT" => recurrence.count = Some(number("COUNT", value, 1, 999)?),
+                "UNTIL" => recurrence.until = Some(until(value)?),
+                _ => return Err(format!("'{name}' is not supported")),
+            }
+        }
+        let Some(frequency) = frequency else {
+            return Err("FREQ is required".into());
+        };
+        recurrence.frequency = frequency;
+        if !recurrence.by_day.is_empty() && frequency != Frequency::Weekly {
+            return Err("BYDAY is only supported with FREQ=WEEKLY".into());
+        }
+        if recurrence.by_month_day.is_some() && frequency != Frequency::Monthly {
+            return Err("BYMONTHDAY is only supported with FREQ=MONTHLY".into());
+        }
+        if recurrence.count.is_some() && recurrence.until.is_some() {
+            return Err("COUNT and UNTIL cannot be combined".into());
+        }
+        Ok(recurrence)
+    }
+}
+
+// The canonical form the rule is stored and sent in.
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+        write!(f, "FREQ={frequency}")?;
+        if self.interval > 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<&str> = self
+                .by_day
+                .iter()
+                .map(|day| WEEKDAYS[day.number_days_from_monday() as usize].0)
+                .collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if let Some(day) = self.by_month_day {
+            write!(f, ";BYMONTHDAY={day}")?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+        if let Some(until) = self.until {
+            let until = until
+                .format(format_description!(
+                    "[year][month][day]T[hour][minute][second]Z"
+                ))
+                .unwrap();
+            write!(f, ";UNTIL={until}")?;
+        }
+        Ok(())
+    }
+}
+
+fn month_date(year: i32, month: u8, day: i8) -> Option<Date> {
+    let month = Month::try_from(month).ok()?;
+    let last = month.length(year);
+    let day = if day < 0 {
+        last
+    } else {
+        u8::try_from(day).ok()?
+    };
+    Date::from_calendar_date(year, month, day).ok()
+}
+
+impl Recurrence {
+    // The occurrence right after `current`, keeping its time of day. Months
+    // and years lacking the day are passed over, as RFC 5545 does.
+    fn following(&self, current: OffsetDateTime) -> Option<OffsetDateTime> {
+        let date = current.date();
+        let interval = i64::from(self.interval);
+        let next = match self.frequency {
+            Frequency::Daily => date.checked_add(Duration::days(interval)),
+            Frequency::Weekly if self.by_day.is_empty() => {
+                date.checked_add(Duration::weeks(interval))
+            }
+            Frequency::Weekly => {
+                let monday = |date: Date| {
+                    date - Duration::days(date.weekday().number_days_from_monday().into())
+                };
+                let week = monday(date);
+                (1..=7 * (interval + 1))
+                    .filter_map(|days| date.checked_add(Duration::days(days)))
+                    .find(|next| {
+                        (monday(*next) - week).whole_weeks() % interval == 0
+                            && self.by_day.contains(&next.weekday())
+                    })
+            }
+            Frequency::Monthly => {
+                let day = self.by_month_day.unwrap_or(date.day() as i8);
+                let start = date.year() * 12 + i32::from(u8::from(date.month())) - 1;
+                (0..1000)
+                    .map(|step| start + step * self.interval as i32)
+                    .filter_map(|month| {
+                        month_date(month.div_euclid(12), (month.rem_euclid(12) + 1) as u8, day)
+                    })
+                    .find(|next| *next > date)
+            }
+            Frequency::Yearly => (1..1000)
+                .map(|step| date.year() + step * self.interval as i32)
+                .find_map(|year| Date::from_calendar_date(year, date.month(), date.day()).ok()),
+        }?;
+        Some(
+            next.with_time(current.time())
+                .assume_offset(current.offset()),
+        )
+    }
+
+    // The first occurrence after both `current` and `now`, with the rule that
+    // applies from there on; `None` once the series has ended. Occurrences
+    // passed over count against COUNT.
+    fn advance(
+        &self,
+        current: OffsetDateTime,
+        now: OffsetDateTime,
+    ) -> Option<(OffsetDateTime, Recurrence)> {
+        let mut next = current;
+        let mut count = self.count;
+        loop {
+            count = match count {
+                Some(1) => return None,
+                count => count.map(|count| count - 1),
+            };
+            next = self.following(next)?;
+            if self.until.is_some_and(|until| next > until) {
+                return None;
+            }
+            if next > now {
+                break;
+            }
+        }
+        Some((
+            next,
+            Recurrence {
+                count,
+                ..self.clone()
+            },
+        ))
+    }
+}
+
+// Where a recurring task moves to for its next occurrence.
+pub struct Rescheduled {
+    pub due_at: Option<String>,
+    pub start_at: Option<String>,
+    pub recurrence: String,
+}
+
+// Works out the next occurrence of a task, counting from its due date, its
+// start date or else from now. The start date keeps its distance to the due
+// date. Weekdays and month ends are taken in the client's `offset`, unless the
+// dates are all-day or floating and so already read as local.
+pub fn reschedule(task: &Task, offset: UtcOffset) -> Option<Rescheduled> {
+    let recurrence: Recurrence = task.recurrence.as_deref()?.parse().ok()?;
+    let offset = match (task.due_kind, task.start_kind) {
+        (None, None) => offset,
+        _ => UtcOffset::UTC,
+    };
+    let parse = |value: &Option<String>| {
+        value
+            .as_deref()
+            .and_then(|value| schedule::parse(value).ok())
+            .map(|value| value.to_offset(offset))
+    };
+    let (due_at, start_at) = (parse(&task.due_at), parse(&task.start_at));
+    let now = OffsetDateTime::now_utc().to_offset(offset);
+    let current = due_at.or(start_at).unwrap_or(now);
+    let (next, recurrence) = recurrence.advance(current, now)?;
+    let shift = next - current;
+    Some(Rescheduled {
+        due_at: match (due_at, start_at) {
+            (None, Some(_)) => None,
+            (due_at, _) => Some(schedule::format(
+                due_at.map_or(next, |due_at| due_at + shift),
+            )),
+        },
+        start_at: start_at.map(|start_at| schedule::format(start_at + shift)),
+        recurrence: recurrence.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn rule(value: &str) -> Recurrence {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn rules_round_trip_in_canonical_form() {
+        for (value, canonical) in [
+            ("FREQ=DAILY", "FREQ=DAILY"),
+            (
+                "rrule:freq=weekly;byday=fr,mo,mo",
+                "FREQ=WEEKLY;BYDAY=MO,FR",
+            ),
+            (
+                "FREQ=MONTHLY;INTERVAL=1;BYMONTHDAY=-1",
+                "FREQ=MONTHLY;BYMONTHDAY=-1",
+            ),
+            (
+                "FREQ=YEARLY;INTERVAL=2;COUNT=3",
+                "FREQ=YEARLY;INTERVAL=2;COUNT=3",
+            ),
+            (
+                "FREQ=DAILY;UNTIL=20261231",
+                "FREQ=DAILY;UNTIL=20261231T235959Z",
+            ),
+        ] {
+            assert_eq!(rule(value).to_string(), canonical);
+            assert_eq!(rule(canonical).to_string(), canonical);
+        }
+    }
+
+    #[test]
+    fn unsupported_rules_are_rejected() {
+        for value in [
+            "",
+            "INTERVAL=2",
+            "FREQ=HOURLY",
+            "FREQ=DAILY;BYDAY=MO",
+            "FREQ=WEEKLY;BYMONTHDAY=1",
+            "FREQ=MONTHLY;BYMONTHDAY=0",
+            "FREQ=DAILY;COUNT=2;UNTIL=20261231",
+            "FREQ=DAILY;BYHOUR=9",
+        ] {
+            assert!(value.parse::<Recurrence>().is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn following_keeps_the_time_of_day() {
+        let current = datetime!(2026-10-19 09:30 +02:00);
+        assert_eq!(
+            rule("FREQ=DAILY;INTERVAL=3").following(current),
+            Some(datetime!(2026-10-22 09:30 +02:00))
+        );
+        assert_eq!(
+            rule("FREQ=WEEKLY").following(current),
+            Some(datetime!(2026-10-26 09:30 +02:00))
+        );
+    }
+
+    #[test]
+    fn following_picks_the_next_listed_weekday() {
+        // 2026-10-19 is a Monday
+        let weekly = rule("FREQ=WEEKLY;BYDAY=MO,TH");
+        assert_eq!(
+            weekly.following(datetime!(2026-10-19 08:00 UTC)),
+            Some(datetime!(2026-10-22 08:00 UTC))
+        );
+        assert_eq!(
+            weekly.following(datetime!(2026-10-22 08:00 UTC)),
+            Some(datetime!(2026-10-26 08:00 UTC))
+        );
+        let fortnightly = rule("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH");
+        assert_eq!(
+            fortnightly.following(datetime!(2026-10-22 08:00 UTC)),
+            Some(datetime!(2026-11-02 08:00 UTC))
+        );
+    }
+
+    #[test]
+    fn following_passes_over_missing_days() {
+        assert_eq!(
+            rule("FREQ=MONTHLY").following(datetime!(2026-01-31 08:00 UTC)),
+            Some(datetime!(2026-03-31 08:00 UTC))
+        );
+        assert_eq!(
+            rule("FREQ=MONTHLY;BYMONTHDAY=-1").following(datetime!(2026-01-31 08:00 UTC)),
+            Some(datetime!(2026-02-28 08:00 UTC))
+        );
+        assert_eq!(
+            rule("FREQ=YEARLY").following(datetime!(2024-02-29 08:00 UTC)),
+            Some(datetime!(2028-02-29 08:00 UTC))
+        );
+    }
+
+    #[test]
+    fn advance_skips_past_occurrences_and_counts_them() {
+        let current = datetime!(2026-10-01 08:00 UTC);
+        let now = datetime!(2026-10-03 12:00 UTC);
+        let (next, rest) = rule("FREQ=DAILY;COUNT=5").advance(current, now).unwrap();
+        assert_eq!(next, datetime!(2026-10-04 08:00 UTC));
+        assert_eq!(rest.to_string(), "FREQ=DAILY;COUNT=2");
+        assert!(rule("FREQ=DAILY;COUNT=3").advance(current, now).is_none());
+    }
+
+    #[test]
+    fn advance_ends_at_until() {
+        let current = datetime!(2026-10-01 08:00 UTC);
+        let recurrence = rule("FREQ=DAILY;UNTIL=20261002");
+        assert_eq!(
+            recurrence.advance(current, current).map(|(next, _)| next),
+            Some(datetime!(2026-10-02 08:00 UTC))
+        );
+        let later = datetime!(2026-10-02 08:00 UTC);
+        assert!(recurrence.advance(later, later).is_none());
+    }
+}
@@ -0,0 +1,55 @@
+use serde::Serialize;
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime, UtcOffset,
+};
+
+use crate::db::Task;
+
+// Ids of the open tasks with a due date, split around the client's current day.
+#[derive(Serialize, Default)]
+pub struct TaskGroups {
+    pub overdue: Vec<i64>,
+    pub today: Vec<i64>,
+    pub upcoming: Vec<i64>,
+}
+
+impl TaskGroups {
+    pub fn new(tasks: &[Task], offset: UtcOffset) -> TaskGroups {
+        let now = OffsetDateTime::now_utc().to_offset(offset);
+        let mut groups = TaskGroups::default();
+        for task in tasks.iter().filter(|task| !task.done) {
+            let Some(due_at) = task.due_at.as_deref().and_then(|due_at| parse(due_at).ok()) else {
+                continue;
+            };
+            let due_at = due_at.to_offset(offset);
+            if due_at < now {
+                groups.overdue.push(task.task_id);
+            } else if due_at.date() == now.date() {
+                groups.today.push(task.task_id);
+            } else {
+                groups.upcoming.push(task.task_id);
+            }
+        }
+        groups
+    }
+}
+
+pub fn parse(value: &str) -> Result<OffsetDateTime, time::error::Parse> {
+    OffsetDateTime::parse(value, &Rfc3339)
+}
+
+// Accepts any RFC 3339 timestamp and stores it as UTC with second precision,
+// which keeps the column sortable as plain text.
+pub fn normalize(value: &str) -> Result<String, String> {
+    let date_time = parse(value).map_err(|_| format!("'{value}' is not an RFC 3339 date"))?;
+    Ok(format(date_time))
+}
+
+pub fn format(date_time: OffsetDateTime) -> String {
+    date_time
+        .to_offset(UtcOffset::UTC)
+        .format(format_description!(
+            "[year]-[month]-[day]T[hour]:[minute]:[second]Z"
+        ))
+        .unwrap()
+}
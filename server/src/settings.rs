@@ -0,0 +1,174 @@
+use std::{
+    env, fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+use actix_web::cookie::Key;
+use serde::Deserialize;
+
+const DEFAULT_CONFIG_PATH: &str = "rustodo.toml";
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub bind_address: String,
+    pub port: u16,
+    pub database_path: PathBuf,
+    pub database_pool_size: usize,
+    pub session_key: String,
+    // Everything in it is served to anyone, so it must not hold the database
+    // or the config file.
+    pub static_dir: PathBuf,
+    // An empty list allows any origin.
+    pub cors_origins: Vec<String>,
+    // The file the settings were read from, if any.
+    #[serde(skip)]
+    config_path: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            bind_address: "127.0.0.1".to_string(),
+            port: 8080,
+            database_path: PathBuf::from("db.sql"),
+            database_pool_size: 4,
+            session_key: "".to_string(),
+            static_dir: PathBuf::from("../client/dist"),
+            cors_origins: vec![],
+            config_path: None,
+        }
+    }
+}
+
+impl Settings {
+    // Reads the TOML file named by RUSTODO_CONFIG (or ./rustodo.toml when present),
+    // then applies RUSTODO_* environment overrides and validates the result.
+    pub fn load() -> Result<Settings, Vec<String>> {
+        let mut settings = match env::var("RUSTODO_CONFIG") {
+            Ok(path) => Settings::from_file(Path::new(&path))?,
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Settings::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            Err(_) => Settings::default(),
+        };
+
+        let mut errors: Vec<String> = vec![];
+        if let Ok(value) = env::var("RUSTODO_BIND_ADDRESS") {
+            settings.bind_address = value;
+        }
+        if let Ok(value) = env::var("RUSTODO_PORT") {
+            match value.parse() {
+                Ok(port) => settings.port = port,
+                Err(_) => errors.push(format!("RUSTODO_PORT: '{value}' is not a valid port")),
+            }
+        }
+        if let Ok(value) = env::var("RUSTODO_DATABASE_PATH") {
+            settings.database_path = PathBuf::from(value);
+        }
+        if let Ok(value) = env::var("RUSTODO_DATABASE_POOL_SIZE") {
+            match value.parse() {
+                Ok(size) => settings.database_pool_size = size,
+                Err(_) => errors.push(format!(
+                    "RUSTODO_DATABASE_POOL_SIZE: '{value}' is not a number"
+                )),
+            }
+        }
+        if let Ok(value) = env::var("RUSTODO_SESSION_KEY") {
+            settings.session_key = value;
+        }
+        if let Ok(value) = env::var("RUSTODO_STATIC_DIR") {
+            settings.static_dir = PathBuf::from(value);
+        }
+        if let Ok(value) = env::var("RUSTODO_CORS_ORIGINS") {
+            settings.cors_origins = value
+                .split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect();
+        }
+
+        errors.extend(settings.validate());
+        if errors.is_empty() {
+            Ok(settings)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn from_file(path: &Path) -> Result<Settings, Vec<String>> {
+        let contents =
+            fs::read_to_string(path).map_err(|err| vec![format!("{}: {err}", path.display())])?;
+        let settings: Settings =
+            toml::from_str(&contents).map_err(|err| vec![format!("{}: {err}", path.display())])?;
+        Ok(Settings {
+            config_path: Some(path.to_path_buf()),
+            ..settings
+        })
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut errors: Vec<String> = vec![];
+        if self.bind_address.parse::<IpAddr>().is_err() {
+            errors.push(format!(
+                "bind_address: '{}' is not an IP address",
+                self.bind_address
+            ));
+        }
+        if self.database_pool_size == 0 {
+            errors.push("database_pool_size: must be at least 1".to_string());
+        }
+        if self.session_key.is_empty() {
+            errors.push("session_key: must be set (or use RUSTODO_SESSION_KEY)".to_string());
+        } else if self.session_key.len() < 64 {
+            errors.push("session_key: must be at least 64 bytes long".to_string());
+        }
+        match self.static_dir.canonicalize() {
+            Ok(static_dir) if static_dir.is_dir() => {
+                let files = [
+                    ("database_path", Some(&self.database_path)),
+                    ("config file", self.config_path.as_ref()),
+                ];
+                for (name, path) in files {
+                    let Some(path) = path else {
+                        continue;
+                    };
+                    if absolute(path).is_some_and(|path| path.starts_with(&static_dir)) {
+                        errors.push(format!(
+                            "static_dir: '{}' holds the {name} '{}', which would be served to anyone",
+                            self.static_dir.display(),
+                            path.display()
+                        ));
+                    }
+                }
+            }
+            _ => errors.push(format!(
+                "static_dir: '{}' is not a directory",
+                self.static_dir.display()
+            )),
+        }
+        for origin in &self.cors_origins {
+            if !origin.starts_with("http://") && !origin.starts_with("https://") {
+                errors.push(format!(
+                    "cors_origins: '{origin}' must start with http(s)://"
+                ));
+            }
+        }
+        errors
+    }
+
+    pub fn session_key(&self) -> Key {
+        Key::from(self.session_key.as_bytes())
+    }
+}
+
+// The absolute form of a path that need not exist yet, as long as its
+// directory does.
+fn absolute(path: &Path) -> Option<PathBuf> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Some(directory.canonicalize().ok()?.join(path.file_name()?))
+}
@@ -0,0 +1,218 @@
+use actix_web::{
+    delete, get,
+    http::header,
+    patch, post,
+    web::{self, Data, Json},
+    HttpResponse, Result,
+};
+use serde::{Deserialize, Deserializer};
+
+use crate::auth::CurrentUser;
+use crate::db::{TaskEdit, UserTasksDB};
+use crate::{task_changes, InvalidInput, ListQuery, ResponseTask, ResponseView, TaskInfo};
+
+// Body of `PATCH /tasks/{id}`: only the fields present are changed. A date sent
+// as null is cleared, a missing one is kept.
+#[derive(Deserialize)]
+pub struct TaskPatch {
+    task_title: Option<String>,
+    task_description: Option<String>,
+    done: Option<bool>,
+    #[serde(default, deserialize_with = "present")]
+    due_at: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    start_at: Option<Option<String>>,
+    priority: Option<i64>,
+    tags: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "present")]
+    recurrence: Option<Option<String>>,
+    list_id: Option<i64>,
+}
+
+// Tells a field sent as null apart from a missing one.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+fn location(task_id: i64) -> String {
+    format!("/tasks/{task_id}")
+}
+
+#[get("/tasks/{task_id}")]
+async fn task_get(
+    user_tasks_db: Data<UserTasksDB>,
+    task_id: web::Path<i64>,
+    user: CurrentUser,
+) -> Result<Json<ResponseTask>> {
+    let task = user_tasks_db.get_task(*task_id, user.user_id).await?;
+    Ok(Json(ResponseTask::from(task)))
+}
+
+#[post("/tasks")]
+async fn task_post(
+    user_tasks_db: Data<UserTasksDB>,
+    task_info: web::Json<TaskInfo>,
+    user: CurrentUser,
+    list_query: web::Query<ListQuery>,
+) -> Result<HttpResponse> {
+    let task = task_info.to_input()?;
+    let (task_id, revision) = user_tasks_db
+        .create_task(
+            user.user_id,
+            task_info.list_id,
+            task_info.parent_id,
+            task,
+            task_info.done,
+        )
+        .await?;
+    let mut response = HttpResponse::Created();
+    response.insert_header((header::LOCATION, location(task_id)));
+    Ok(match list_query.view {
+        ResponseView::Full => {
+            let task = user_tasks_db.get_task(task_id, user.user_id).await?;
+            response.json(ResponseTask::from(task))
+        }
+        ResponseView::Changes => {
+            let affected = user_tasks_db.task_family(task_id, user.user_id).await?;
+            response.json(
+                task_changes(
+                    &user_tasks_db,
+                    user.user_id,
+                    &list_query,
+                    affected,
+                    revision,
+                    "Create task: successful!".to_string(),
+                )
+                .await?,
+            )
+        }
+    })
+}
+
+#[patch("/tasks/{task_id}")]
+async fn task_patch(
+    user_tasks_db: Data<UserTasksDB>,
+    task_id: web::Path<i64>,
+    task_patch: web::Json<TaskPatch>,
+    user: CurrentUser,
+    list_query: web::Query<ListQuery>,
+) -> Result<HttpResponse> {
+    let task_id = task_id.into_inner();
+    let task_patch = task_patch.into_inner();
+    let affected = user_tasks_db.task_family(task_id, user.user_id).await?;
+
+    // the patch is merged into the stored task and checked as a whole
+    let revision = user_tasks_db
+        .patch_task(
+            task_id,
+            user.user_id,
+            move |current| {
+                let task = TaskInfo {
+                    task_id,
+                    list_id: None,
+                    parent_id: None,
+                    task_title: task_patch.task_title.unwrap_or(current.title.clone()),
+                    task_description: task_patch
+                        .task_description
+                        .unwrap_or(current.description.clone()),
+                    done: current.done,
+                    due_at: task_patch.due_at.unwrap_or(current.due_at.clone()),
+                    start_at: task_patch.start_at.unwrap_or(current.start_at.clone()),
+                    priority: task_patch.priority.unwrap_or(current.priority),
+                    tags: task_patch.tags,
+                    recurrence: task_patch.recurrence.unwrap_or(current.recurrence.clone()),
+                }
+                .to_input()?;
+                Ok::<_, InvalidInput>(TaskEdit {
+                    list_id: task_patch.list_id,
+                    task,
+                    done: task_patch.done,
+                })
+            },
+            list_query.offset(),
+        )
+        .await??;
+
+    Ok(match list_query.view {
+        ResponseView::Full => {
+            let task = user_tasks_db.get_task(task_id, user.user_id).await?;
+            HttpResponse::Ok().json(ResponseTask::from(task))
+        }
+        ResponseView::Changes => HttpResponse::Ok().json(
+            task_changes(
+                &user_tasks_db,
+                user.user_id,
+                &list_query,
+                affected,
+                revision,
+                "Update task: successful!".to_string(),
+            )
+            .await?,
+        ),
+    })
+}
+
+#[delete("/tasks/{task_id}")]
+async fn task_delete(
+    user_tasks_db: Data<UserTasksDB>,
+    task_id: web::Path<i64>,
+    user: CurrentUser,
+    list_query: web::Query<ListQuery>,
+) -> Result<HttpResponse> {
+    let task_id = task_id.into_inner();
+    let affected = user_tasks_db.task_family(task_id, user.user_id).await?;
+    let revision = user_tasks_db.delete_task(task_id, user.user_id).await?;
+
+    // without `view=changes` there is nothing left to send
+    Ok(match list_query.view {
+        ResponseView::Full => HttpResponse::NoContent().finish(),
+        ResponseView::Changes => HttpResponse::Ok().json(
+            task_changes(
+                &user_tasks_db,
+                user.user_id,
+                &list_query,
+                affected,
+                revision,
+                "Delete task: successful!".to_string(),
+            )
+            .await?,
+        ),
+    })
+}
+
+// Passes over the current occurrence of a recurring task without completing it.
+#[post("/tasks/{task_id}/skip")]
+async fn task_skip(
+    user_tasks_db: Data<UserTasksDB>,
+    task_id: web::Path<i64>,
+    user: CurrentUser,
+    list_query: web::Query<ListQuery>,
+) -> Result<HttpResponse> {
+    let task_id = task_id.into_inner();
+    let affected = user_tasks_db.task_family(task_id, user.user_id).await?;
+    let revision = user_tasks_db
+        .skip_occurrence(task_id, user.user_id, list_query.offset())
+        .await?;
+
+    Ok(match list_query.view {
+        ResponseView::Full => {
+            let task = user_tasks_db.get_task(task_id, user.user_id).await?;
+            HttpResponse::Ok().json(ResponseTask::from(task))
+        }
+        ResponseView::Changes => HttpResponse::Ok().json(
+            task_changes(
+                &user_tasks_db,
+                user.user_id,
+                &list_query,
+                affected,
+                revision,
+                "Skip occurrence: successful!".to_string(),
+            )
+            .await?,
+        ),
+    })
+}
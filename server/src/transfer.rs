@@ -0,0 +1,561 @@
+use std::collections::{HashMap, HashSet};
+
+use actix_web::{
+    get,
+    http::header,
+    web::{self, Data, Json},
+    HttpResponse, Result,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::auth::CurrentUser;
+use crate::db::{ImportIssue, ImportTask, Task, TaskFilter, TaskSort, UserTasksDB};
+use crate::error;
+use crate::schedule;
+use crate::{normalize_list_name, InvalidInput, TaskInfo};
+
+// Identifies export documents, along with VERSION.
+const FORMAT: &str = "rustodo-tasks";
+// Bumped whenever a field changes meaning or a required one is added, so that
+// older servers refuse documents they would misread.
+const VERSION: u32 = 1;
+
+// Exports can be much larger than the usual request bodies.
+pub const IMPORT_LIMIT: usize = 16 * 1024 * 1024;
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Format {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: Format,
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    format: Format,
+    // reports what importing would do without changing anything
+    #[serde(default)]
+    dry_run: bool,
+}
+
+// A task in an export. `id` only links subtasks to their parent within the
+// document; imported tasks get new ids.
+#[derive(Serialize, Deserialize)]
+struct Row {
+    id: i64,
+    #[serde(default)]
+    parent_id: Option<i64>,
+    #[serde(default)]
+    list: Option<String>,
+    title: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    completed_at: Option<String>,
+    #[serde(default)]
+    due_at: Option<String>,
+    #[serde(default)]
+    start_at: Option<String>,
+    #[serde(default)]
+    priority: i64,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    recurrence: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Document {
+    format: String,
+    version: u32,
+    #[serde(default)]
+    exported_at: Option<String>,
+    tasks: Vec<Row>,
+}
+
+// The same fields as `Row`, one line each, with the version repeated on every
+// line and the tags comma separated, which tag names cannot contain.
+#[derive(Serialize, Deserialize)]
+struct CsvRow {
+    version: u32,
+    id: i64,
+    parent_id: Option<i64>,
+    list: Option<String>,
+    title: String,
+    description: Option<String>,
+    done: Option<bool>,
+    completed_at: Option<String>,
+    due_at: Option<String>,
+    start_at: Option<String>,
+    priority: Option<i64>,
+    tags: Option<String>,
+    recurrence: Option<String>,
+}
+
+impl From<Row> for CsvRow {
+    fn from(row: Row) -> Self {
+        CsvRow {
+            version: VERSION,
+            id: row.id,
+            parent_id: row.parent_id,
+            list: row.list,
+            title: row.title,
+            description: Some(row.description),
+            done: Some(row.done),
+            completed_at: row.completed_at,
+            due_at: row.due_at,
+            start_at: row.start_at,
+            priority: Some(row.priority),
+            tags: Some(row.tags.join(",")),
+            recurrence: row.recurrence,
+        }
+    }
+}
+
+impl From<CsvRow> for Row {
+    fn from(row: CsvRow) -> Self {
+        Row {
+            id: row.id,
+            parent_id: row.parent_id,
+            list: row.list,
+            title: row.title,
+            description: row.description.unwrap_or_default(),
+            done: row.done.unwrap_or_default(),
+            completed_at: row.completed_at,
+            due_at: row.due_at,
+            start_at: row.start_at,
+            priority: row.priority.unwrap_or_default(),
+            tags: row
+                .tags
+                .unwrap_or_default()
+                .split(',')
+                .filter(|tag| !tag.trim().is_empty())
+                .map(str::to_string)
+                .collect(),
+            recurrence: row.recurrence,
+        }
+    }
+}
+
+// Parents come right before their subtasks, each group in the order the user
+// arranged it, so that importing keeps that order.
+fn rows(mut tasks: Vec<Task>, list_names: &HashMap<i64, String>) -> Vec<Row> {
+    tasks.sort_by_key(|task| (task.list_id, task.position));
+    let mut ordered: Vec<&Task> = vec![];
+    for parent in tasks.iter().filter(|task| task.parent_id.is_none()) {
+        ordered.push(parent);
+        ordered.extend(
+            tasks
+                .iter()
+                .filter(|task| task.parent_id == Some(parent.task_id)),
+        );
+    }
+    ordered
+        .into_iter()
+        .map(|task| Row {
+            id: task.task_id,
+            parent_id: task.parent_id,
+            list: list_names.get(&task.list_id).cloned(),
+            title: task.title.clone(),
+            description: task.description.clone(),
+            done: task.done,
+            completed_at: task.completed_at.clone(),
+            due_at: task.due_at.clone(),
+            start_at: task.start_at.clone(),
+            priority: task.priority,
+            tags: task.tags.clone(),
+            recurrence: task.recurrence.clone(),
+        })
+        .collect()
+}
+
+#[get("/export")]
+async fn export(
+    user_tasks_db: Data<UserTasksDB>,
+    query: web::Query<ExportQuery>,
+    user: CurrentUser,
+) -> Result<HttpResponse> {
+    let tasks = user_tasks_db
+        .get_tasks_by_user_id(user.user_id, TaskSort::default(), TaskFilter::default())
+        .await?;
+    let list_names = user_tasks_db
+        .get_lists_by_user_id(user.user_id)
+        .await?
+        .into_iter()
+        .map(|list| (list.list_id, list.name))
+        .collect();
+    let rows = rows(tasks, &list_names);
+    let (content_type, extension, body) = match query.format {
+        Format::Json => {
+            let document = Document {
+                format: FORMAT.to_string(),
+                version: VERSION,
+                exported_at: Some(schedule::format(OffsetDateTime::now_utc())),
+                tasks: rows,
+            };
+            let body = serde_json::to_string_pretty(&document).unwrap();
+            ("application/json", "json", body)
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(vec![]);
+            for row in rows {
+                writer.serialize(CsvRow::from(row)).unwrap();
+            }
+            let body = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+            ("text/csv; charset=utf-8", "csv", body)
+        }
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"tasks.{extension}\""),
+        ))
+        .body(body))
+}
+
+fn unreadable(message: impl Into<String>) -> InvalidInput {
+    InvalidInput::field("body", message)
+}
+
+fn check_version(version: u32) -> Result<(), InvalidInput> {
+    match version {
+        VERSION => Ok(()),
+        version => Err(unreadable(format!(
+            "version {version} of the export format is not supported, only {VERSION} is"
+        ))),
+    }
+}
+
+fn parse_document(format: Format, body: &str) -> Result<Vec<Row>, InvalidInput> {
+    match format {
+        Format::Json => {
+            let document: Document = serde_json::from_str(body)
+                .map_err(|err| unreadable(format!("the document is not a task export: {err}")))?;
+            if document.format != FORMAT {
+                return Err(unreadable(format!(
+                    "the document is not a task export, its format is '{}'",
+                    document.format
+                )));
+            }
+            check_version(document.version)?;
+            Ok(document.tasks)
+        }
+        Format::Csv => {
+            let mut rows = vec![];
+            for row in csv::Reader::from_reader(body.as_bytes()).deserialize::<CsvRow>() {
+                let row =
+                    row.map_err(|err| unreadable(format!("the CSV is not a task export: {err}")))?;
+                check_version(row.version)?;
+                rows.push(Row::from(row));
+            }
+            Ok(rows)
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ResponseImportIssue {
+    // 1 for the first task of the document
+    row: usize,
+    title: String,
+    message: String,
+}
+
+impl From<ImportIssue> for ResponseImportIssue {
+    fn from(issue: ImportIssue) -> Self {
+        ResponseImportIssue {
+            row: issue.row,
+            title: issue.title,
+            message: issue.message,
+        }
+    }
+}
+
+// Nothing is imported while any row has errors.
+#[derive(Serialize)]
+pub struct ImportResponse {
+    dry_run: bool,
+    imported: bool,
+    created: usize,
+    lists_created: Vec<String>,
+    duplicates: Vec<ResponseImportIssue>,
+    errors: Vec<ResponseImportIssue>,
+}
+
+// Validates a row as the task routes would, plus the fields only imports set.
+fn import_task(row: usize, task: Row) -> Result<ImportTask, Vec<String>> {
+    let info = TaskInfo {
+        task_id: 0,
+        list_id: None,
+        parent_id: None,
+        task_title: task.title,
+        task_description: task.description,
+        done: task.done,
+        due_at: task.due_at,
+        start_at: task.start_at,
+        priority: task.priority,
+        tags: Some(task.tags),
+        recurrence: task.recurrence,
+    };
+    let mut errors = vec![];
+    let input = info
+        .to_input()
+        .map_err(|InvalidInput(fields)| {
+            errors.extend(fields.into_iter().map(|field| field.message));
+        })
+        .ok();
+    let list = match task.list.filter(|name| !name.trim().is_empty()) {
+        Some(name) => normalize_list_name(&name)
+            .map_err(|InvalidInput(fields)| {
+                errors.extend(fields.into_iter().map(|field| field.message));
+            })
+            .ok(),
+        None => None,
+    };
+    let completed_at = match task.completed_at.filter(|value| !value.is_empty()) {
+        Some(value) => schedule::normalize(&value)
+            .map_err(|message| errors.push(message))
+            .ok(),
+        None => None,
+    };
+    match input {
+        Some(input) if errors.is_empty() => Ok(ImportTask {
+            row,
+            key: task.id,
+            parent_key: task.parent_id,
+            list,
+            task: input,
+            done: task.done,
+            completed_at,
+        }),
+        _ => Err(errors),
+    }
+}
+
+// Checks every row of a document, returning the rows that can be imported,
+// parents first, and the problems with the others. Ids must be unique, as
+// subtasks refer to their parent by id.
+fn check_rows(rows: Vec<Row>) -> Result<(Vec<ImportTask>, Vec<ResponseImportIssue>), InvalidInput> {
+    let mut keys = HashSet::new();
+    if let Some(row) = rows.iter().find(|row| !keys.insert(row.id)) {
+        return Err(unreadable(format!(
+            "the id {} is used by more than one task",
+            row.id
+        )));
+    }
+
+    let mut errors = vec![];
+    let mut error = |row: usize, title: &str, message: String| {
+        errors.push(ResponseImportIssue {
+            row,
+            title: title.to_string(),
+            message,
+        })
+    };
+    // subtasks cannot have subtasks of their own
+    let top_level: HashSet<i64> = rows
+        .iter()
+        .filter(|row| row.parent_id.is_none())
+        .map(|row| row.id)
+        .collect();
+    let mut tasks = vec![];
+    for (index, row) in rows.into_iter().enumerate() {
+        let title = row.title.clone();
+        match row.parent_id {
+            Some(parent_id) if !keys.contains(&parent_id) => {
+                error(
+                    index + 1,
+                    &title,
+                    format!("no task has the parent id {parent_id}"),
+                );
+                continue;
+            }
+            Some(parent_id) if !top_level.contains(&parent_id) => {
+                error(
+                    index + 1,
+                    &title,
+                    "subtasks cannot have subtasks".to_string(),
+                );
+                continue;
+            }
+            _ => {}
+        }
+        match import_task(index + 1, row) {
+            Ok(task) => tasks.push(task),
+            Err(messages) => {
+                for message in messages {
+                    error(index + 1, &title, message);
+                }
+            }
+        }
+    }
+    // a subtask can only be imported along with its parent
+    let parent_keys: HashSet<i64> = tasks
+        .iter()
+        .filter(|task| task.parent_key.is_none())
+        .map(|task| task.key)
+        .collect();
+    tasks.retain(|task| match task.parent_key {
+        Some(parent_key) if !parent_keys.contains(&parent_key) => {
+            error(
+                task.row,
+                &task.task.title,
+                format!("its parent (id {parent_key}) cannot be imported"),
+            );
+            false
+        }
+        _ => true,
+    });
+    tasks.sort_by_key(|task| task.parent_key.is_some());
+    errors.sort_by_key(|issue| issue.row);
+    Ok((tasks, errors))
+}
+
+// Takes a document made by `export`, in the same format. Every row is checked
+// before anything is written, and all of them go in together or none does.
+pub async fn import(
+    user_tasks_db: Data<UserTasksDB>,
+    query: web::Query<ImportQuery>,
+    body: Result<String, actix_web::Error>,
+    user: CurrentUser,
+) -> Result<Json<ImportResponse>> {
+    let body = error::text_body(body)?;
+    let (tasks, errors) = check_rows(parse_document(query.format, &body)?)?;
+    if !errors.is_empty() {
+        return Ok(Json(ImportResponse {
+            dry_run: query.dry_run,
+            imported: false,
+            created: 0,
+            lists_created: vec![],
+            duplicates: vec![],
+            errors,
+        }));
+    }
+
+    let commit = !query.dry_run;
+    let report = user_tasks_db
+        .import_tasks(user.user_id, tasks, commit)
+        .await?;
+    Ok(Json(ImportResponse {
+        dry_run: query.dry_run,
+        imported: commit,
+        created: report.created,
+        lists_created: report.lists_created,
+        duplicates: report
+            .duplicates
+            .into_iter()
+            .map(ResponseImportIssue::from)
+            .collect(),
+        errors,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(tasks: &str) -> String {
+        format!(r#"{{"format":"{FORMAT}","version":{VERSION},"tasks":[{tasks}]}}"#)
+    }
+
+    fn check(tasks: &str) -> Result<(Vec<ImportTask>, Vec<ResponseImportIssue>), InvalidInput> {
+        check_rows(parse_document(Format::Json, &document(tasks)).ok().unwrap())
+    }
+
+    fn message(InvalidInput(fields): InvalidInput) -> String {
+        fields.into_iter().map(|field| field.message).collect()
+    }
+
+    #[test]
+    fn rejects_other_documents_and_versions() {
+        for (format, body) in [
+            (Format::Json, "[]".to_string()),
+            (
+                Format::Json,
+                r#"{"format":"other","version":1,"tasks":[]}"#.to_string(),
+            ),
+            (
+                Format::Json,
+                document("").replace(&format!(":{VERSION},"), ":2,"),
+            ),
+            (Format::Csv, "version,id,title\n2,1,Milk\n".to_string()),
+            (Format::Csv, "id,title\n1,Milk\n".to_string()),
+        ] {
+            assert!(parse_document(format, &body).is_err(), "{body}");
+        }
+    }
+
+    #[test]
+    fn reads_csv_with_optional_columns_left_empty() {
+        let rows = parse_document(
+            Format::Csv,
+            "version,id,parent_id,list,title,description,done,completed_at,due_at,start_at,priority,tags,recurrence\n\
+             1,1,,Home,Milk,,,,,,,\"a,b\",\n",
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].list.as_deref(), Some("Home"));
+        assert_eq!(rows[0].tags, ["a", "b"]);
+        assert!(!rows[0].done);
+    }
+
+    #[test]
+    fn rejects_ids_used_twice() {
+        // a second parent with the id of the first would leave its subtask
+        // without a parent to be created under
+        let error = check(
+            r#"{"id":7,"title":"A"},{"id":7,"title":"B"},{"id":8,"parent_id":7,"title":"C"}"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(message(error), "the id 7 is used by more than one task");
+    }
+
+    #[test]
+    fn reports_subtasks_without_an_importable_parent() {
+        let (tasks, errors) = check(
+            r#"{"id":1,"title":""},{"id":2,"parent_id":1,"title":"Child"},
+            {"id":3,"parent_id":9,"title":"Orphan"},
+            {"id":4,"title":"Parent"},{"id":5,"parent_id":4,"title":"Sub"},
+            {"id":6,"parent_id":5,"title":"Subsub"}"#,
+        )
+        .ok()
+        .unwrap();
+        let rows: Vec<usize> = errors.iter().map(|issue| issue.row).collect();
+        assert_eq!(rows, [1, 2, 3, 6]);
+        assert_eq!(errors[1].message, "its parent (id 1) cannot be imported");
+        assert_eq!(errors[2].message, "no task has the parent id 9");
+        assert_eq!(errors[3].message, "subtasks cannot have subtasks");
+        let keys: Vec<i64> = tasks.iter().map(|task| task.key).collect();
+        assert_eq!(keys, [4, 5]);
+    }
+
+    #[test]
+    fn orders_parents_before_subtasks() {
+        let (tasks, errors) = check(
+            r#"{"id":2,"parent_id":1,"title":"Sub"},{"id":1,"title":"Parent","due_at":"2026-10-20T10:00:00+02:00"}"#,
+        )
+        .ok()
+        .unwrap();
+        assert!(errors.is_empty());
+        let keys: Vec<i64> = tasks.iter().map(|task| task.key).collect();
+        assert_eq!(keys, [1, 2]);
+        assert_eq!(
+            tasks[0].task.due_at.as_deref(),
+            Some("2026-10-20T08:00:00Z")
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::error::FieldError;
+
+// Characters a text field may contain.
+#[derive(Clone, Copy)]
+pub enum Charset {
+    // anything but control characters
+    Line,
+    // like `Line`, plus line breaks and tabs
+    Text,
+    // ASCII letters, digits, '_', '-' and '.'
+    Username,
+}
+
+impl Charset {
+    fn allows(self, c: char) -> bool {
+        match self {
+            Charset::Line => !c.is_control(),
+            Charset::Text => !c.is_control() || matches!(c, '\n' | '\r' | '\t'),
+            Charset::Username => c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'),
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Charset::Line | Charset::Text => "must not contain control characters",
+            Charset::Username => "may only contain letters, digits, '_', '-' and '.'",
+        }
+    }
+}
+
+// What a text field has to look like. Lengths count characters, not bytes.
+#[derive(Clone, Copy)]
+pub struct Rule {
+    pub field: &'static str,
+    pub label: &'static str,
+    pub trim: bool,
+    pub min_chars: usize,
+    pub max_chars: usize,
+    pub charset: Charset,
+}
+
+impl Rule {
+    // Trims `value` if the rule asks for it and reports the first thing wrong
+    // with the result.
+    pub fn check(&self, value: &mut String) -> Option<FieldError> {
+        if self.trim {
+            let trimmed = value.trim();
+            if trimmed.len() != value.len() {
+                *value = trimmed.to_string();
+            }
+        }
+        let label = self.label;
+        let chars = value.chars().count();
+        let message = if chars == 0 && self.min_chars > 0 {
+            format!("{label} is required")
+        } else if chars < self.min_chars {
+            format!(
+                "{label} must be at least {} characters long",
+                self.min_chars
+            )
+        } else if chars > self.max_chars {
+            format!("{label} must be at most {} characters long", self.max_chars)
+        } else if !value.chars().all(|c| self.charset.allows(c)) {
+            format!("{label} {}", self.charset.describe())
+        } else {
+            return None;
+        };
+        Some(FieldError::new(self.field, message))
+    }
+}
+
+// Checks every value against its rule, collecting all failures instead of
+// stopping at the first.
+pub fn check(values: &mut [(&Rule, &mut String)]) -> Vec<FieldError> {
+    values
+        .iter_mut()
+        .filter_map(|(rule, value)| rule.check(value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: Rule = Rule {
+        field: "name",
+        label: "the name",
+        trim: true,
+        min_chars: 3,
+        max_chars: 5,
+        charset: Charset::Username,
+    };
+
+    fn message(rule: &Rule, value: &str) -> Option<String> {
+        rule.check(&mut value.to_string())
+            .map(|error| error.message)
+    }
+
+    #[test]
+    fn reports_the_first_problem_with_a_value() {
+        for (value, expected) in [
+            ("abc", None),
+            ("  ab.c_ ", None),
+            ("", Some("the name is required")),
+            ("   ", Some("the name is required")),
+            ("ab", Some("the name must be at least 3 characters long")),
+            ("abcdef", Some("the name must be at most 5 characters long")),
+            (
+                "a b",
+                Some("the name may only contain letters, digits, '_', '-' and '.'"),
+            ),
+        ] {
+            assert_eq!(message(&NAME, value).as_deref(), expected, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn trims_only_when_asked_and_counts_characters() {
+        let mut value = " äöü ".to_string();
+        let rule = Rule {
+            charset: Charset::Line,
+            ..NAME
+        };
+        assert!(rule.check(&mut value).is_none());
+        assert_eq!(value, "äöü");
+
+        let mut value = " äöü ".to_string();
+        let rule = Rule {
+            trim: false,
+            ..rule
+        };
+        assert!(rule.check(&mut value).is_none());
+        assert_eq!(value, " äöü ");
+    }
+
+    #[test]
+    fn only_text_allows_line_breaks() {
+        let line = Rule {
+            charset: Charset::Line,
+            ..NAME
+        };
+        let text = Rule {
+            charset: Charset::Text,
+            ..NAME
+        };
+        assert_eq!(
+            message(&line, "a\nb").as_deref(),
+            Some("the name must not contain control characters")
+        );
+        assert_eq!(message(&text, "a\nb\tc"), None);
+        assert!(message(&text, "a\u{7}b").is_some());
+    }
+
+    #[test]
+    fn collects_every_failing_field() {
+        let (mut name, mut other) = ("x".to_string(), "abc".to_string());
+        let other_rule = Rule {
+            field: "other",
+            max_chars: 2,
+            ..NAME
+        };
+        let errors = check(&mut [(&NAME, &mut name), (&other_rule, &mut other)]);
+        let fields: Vec<&str> = errors.iter().map(|error| error.field.as_str()).collect();
+        assert_eq!(fields, ["name", "other"]);
+    }
+}